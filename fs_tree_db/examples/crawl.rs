@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+
+use tokio::sync::Mutex;
+
+use fs_tree_db::{ excludes::get_excludes, FileMeta, Tree };
+
+/// Times `TreeBuilder` against the crawler it replaced: one `Mutex<VecDeque>` shared by
+/// 1024 tasks per core, each pushing every directory into one `Mutex<Vec>`.
+/// `cargo run --release --example crawl -- <path> [runs]`
+///
+/// Run it twice if the first pass is the one warming the OS cache.
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let root = args
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::current_dir().unwrap());
+    let runs: u32 = args.next().and_then(|n| n.parse().ok()).unwrap_or(5);
+
+    println!("Crawling {} ({} runs each)", root.display(), runs);
+
+    let mut old = Vec::new();
+    let mut new = Vec::new();
+    let mut files = (0, 0);
+
+    for _ in 0..runs {
+        let start = Instant::now();
+        files.0 = mutex_queue(root.clone(), get_excludes()).await;
+        old.push(start.elapsed());
+
+        let start = Instant::now();
+        files.1 = Tree::new(root.clone(), get_excludes()).await.unwrap().len();
+        new.push(start.elapsed());
+    }
+
+    let median = |times: &mut Vec<Duration>| {
+        times.sort();
+        times[times.len() / 2]
+    };
+    let (old, new) = (median(&mut old), median(&mut new));
+
+    println!("| crawler       | files   | median   |");
+    println!("|---------------|---------|----------|");
+    println!("| mutex queue   | {:>7} | {:>5} ms |", files.0, old.as_millis());
+    println!("| work stealing | {:>7} | {:>5} ms |", files.1, new.as_millis());
+    println!("{:.1}x faster", old.as_secs_f64() / new.as_secs_f64());
+}
+
+/// The old crawl, minus building the tree. Returns the number of files found
+async fn mutex_queue(root: PathBuf, exclude: Vec<String>) -> usize {
+    type Listing = Vec<(PathBuf, Option<FileMeta>)>;
+
+    let queue = Arc::new(Mutex::new(VecDeque::from([root])));
+    let data: Arc<Mutex<Vec<Listing>>> = Arc::new(Mutex::new(Vec::new()));
+    let exclude = Arc::new(exclude);
+
+    let num_workers = std::thread::available_parallelism()
+        .map(|n| n.get() * 1024)
+        .unwrap_or(32);
+
+    let mut tasks = Vec::new();
+    for _ in 0..num_workers {
+        let (queue, data, exclude) = (queue.clone(), data.clone(), exclude.clone());
+
+        tasks.push(tokio::spawn(async move {
+            loop {
+                // Gives up as soon as the queue is empty, like the old one did
+                let Some(path) = queue.lock().await.pop_front() else {
+                    break;
+                };
+                let Ok(mut dir) = tokio::fs::read_dir(&path).await else {
+                    continue;
+                };
+
+                let mut listing = Vec::new();
+                while let Ok(Some(entry)) = dir.next_entry().await {
+                    let Ok(file_type) = entry.file_type().await else {
+                        continue;
+                    };
+
+                    if file_type.is_file() {
+                        let meta = entry.metadata().await.ok().map(|m| FileMeta::from(&m));
+                        listing.push((entry.path(), meta));
+                    } else if file_type.is_dir() {
+                        // Excludes are written like `node_modules/` now, the old crawler compared bare names
+                        let name = entry.file_name();
+                        if !exclude.iter().any(|e| e.trim_end_matches('/') == name.to_string_lossy()) {
+                            queue.lock().await.push_back(entry.path());
+                        }
+                    }
+                }
+                data.lock().await.push(listing);
+            }
+        }));
+    }
+
+    for t in tasks {
+        t.await.unwrap();
+    }
+
+    let data = data.lock().await;
+    data.iter().map(Vec::len).sum()
+}
@@ -1,15 +1,16 @@
 use std::{path::PathBuf, time::Instant};
 
-use fs_tree_db::{excludes::get_excludes, Tree};
-
-// const DEFUALT_INITIAL_PATHL: &str = r#"C:\Users\Hyvnt\T"#;
-const DEFUALT_INITIAL_PATHL: &str = r#"C:\"#;
-const DEFAULT_SAVE_PATH: &str =
-    r#"C:\Users\Hyvnt\T\Rust\file-explorer\fs_tree_db\save\tree.bincode"#;
+use fs_tree_db::{excludes::get_excludes, locations, Tree};
 
+/// `cargo run --example demo -- [path]`, indexes the home folder by default
 #[tokio::main]
 async fn main() {
-    let save_path = PathBuf::from(DEFAULT_SAVE_PATH);
+    let root = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(locations::home_dir);
+
+    let save_path = locations::indexes_dir().join("demo.bincode");
 
     // Load tree if already saved
     // if save_path.exists() {
@@ -22,9 +23,9 @@ async fn main() {
     let now = Instant::now(); // start the timer ⏱️
 
     let t = Tree::new(
-        PathBuf::from(DEFUALT_INITIAL_PATHL),
+        root,
         get_excludes()
-    ).await;
+    ).await.unwrap();
 
     let elapsed = now.elapsed(); // stop the timer ⏱️
 
@@ -33,4 +34,5 @@ async fn main() {
     println!("Tree size: {}", t.len());
 
     t.save(&save_path).expect("Couldn't save tree");
+    println!("Saved to {}", save_path.display());
 }
\ No newline at end of file
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use fs_tree_db::{ excludes::get_excludes, Entry, Tree };
+
+/// Compares the node layout `Tree` uses against a flat `Vec<Entry>` of full paths.
+/// `cargo run --release --example layout -- <path>`
+#[tokio::main]
+async fn main() {
+    let root = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::current_dir().unwrap());
+
+    println!("Indexing {}", root.display());
+
+    let tree = Tree::new(root, get_excludes()).await.unwrap();
+
+    let flat: Vec<Entry> = tree
+        .entries()
+        .map(|(path, meta)| Entry { path, meta: meta.cloned() })
+        .collect();
+
+    let flat_heap =
+        flat.capacity() * size_of::<Entry>() +
+        flat
+            .iter()
+            .map(|e| e.path.capacity())
+            .sum::<usize>();
+
+    let flat_disk = bincode::serialize(&flat).unwrap().len();
+    let tree_disk = bincode::serialize(&tree).unwrap().len();
+
+    let mb = |bytes: usize| (bytes as f64) / 1_000_000.0;
+
+    println!("{} files", tree.len());
+    println!("| layout | memory   | on disk  |");
+    println!("|--------|----------|----------|");
+    println!("| flat   | {:>5.1} MB | {:>5.1} MB |", mb(flat_heap), mb(flat_disk));
+    println!("| tree   | {:>5.1} MB | {:>5.1} MB |", mb(tree.heap_size()), mb(tree_disk));
+}
@@ -0,0 +1,96 @@
+use std::fs::Metadata;
+use std::path::{ Path, PathBuf };
+use std::time::SystemTime;
+use serde::{ Serialize, Deserialize };
+
+/// A file in the tree and what was known about it when it was indexed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub path: PathBuf,
+
+    // `None` if the file couldn't be stat'ed while indexing
+    pub meta: Option<FileMeta>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMeta {
+    /// In bytes
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+
+    /// Last status change on Unix (`st_ctime`), creation time elsewhere
+    pub changed: Option<SystemTime>,
+
+    /// Unix permission bits. On other platforms only the write bits are meaningful,
+    /// they're cleared for read-only files
+    pub mode: u32,
+    pub kind: FileKind,
+
+    /// `None` where the platform doesn't expose one
+    pub inode: Option<u64>,
+}
+
+impl Entry {
+    /// Stats `path` to build its entry
+    pub fn read(path: PathBuf) -> Self {
+        let meta = FileMeta::read(&path);
+        Entry { path, meta }
+    }
+}
+
+impl FileMeta {
+    pub fn read(path: &Path) -> Option<Self> {
+        std::fs::symlink_metadata(path).ok().map(|m| FileMeta::from(&m))
+    }
+}
+
+impl From<&Metadata> for FileMeta {
+    fn from(m: &Metadata) -> Self {
+        let file_type = m.file_type();
+        let kind = if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Dir
+        } else if file_type.is_file() {
+            FileKind::File
+        } else {
+            FileKind::Other
+        };
+
+        #[cfg(unix)]
+        let (changed, mode, inode) = {
+            use std::os::unix::fs::MetadataExt;
+
+            let changed = u64::try_from(m.ctime())
+                .ok()
+                .map(|secs| {
+                    SystemTime::UNIX_EPOCH + std::time::Duration::new(secs, m.ctime_nsec() as u32)
+                });
+
+            (changed, m.mode() & 0o7777, Some(m.ino()))
+        };
+
+        #[cfg(not(unix))]
+        let (changed, mode, inode) = {
+            let mode = if m.permissions().readonly() { 0o444 } else { 0o666 };
+            (m.created().ok(), mode, None)
+        };
+
+        FileMeta {
+            size: m.len(),
+            modified: m.modified().ok(),
+            changed,
+            mode,
+            kind,
+            inode,
+        }
+    }
+}
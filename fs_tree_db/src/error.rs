@@ -0,0 +1,108 @@
+use std::fmt;
+use std::path::{ Path, PathBuf };
+
+use serde::{ Serialize, Serializer };
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    PermissionDenied(PathBuf),
+
+    /// Encoding or decoding a saved tree failed
+    Serialization(String),
+
+    /// The build was cancelled before it finished
+    Cancelled,
+
+    /// The OS watcher couldn't be set up
+    Watch(String),
+
+    /// Neither a saved tree nor a legacy one
+    NotAnIndex,
+
+    /// Saved by a version of the format this build can't read
+    UnsupportedVersion { found: u32, supported: u32 },
+
+    /// A saved tree doesn't match its checksum, like when a write was cut short in the body
+    ChecksumMismatch,
+
+    /// A saved tree ends before its header does
+    Truncated,
+
+    /// An exclude pattern that isn't a valid glob
+    InvalidPattern(String),
+}
+
+impl Error {
+    /// Tells permission problems apart from every other IO error
+    pub fn from_io(e: std::io::Error, path: &Path) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::PermissionDenied => Error::PermissionDenied(path.to_path_buf()),
+            _ => Error::Io(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::PermissionDenied(path) => write!(f, "Permission denied: {}", path.display()),
+            Error::Serialization(e) => write!(f, "Index file is corrupt: {}", e),
+            Error::Cancelled => write!(f, "Cancelled"),
+            Error::Watch(e) => write!(f, "Couldn't watch for changes: {}", e),
+            Error::NotAnIndex => write!(f, "Not an index file"),
+            Error::UnsupportedVersion { found, supported } =>
+                write!(f, "Index format v{} is not supported (expected v{})", found, supported),
+            Error::ChecksumMismatch => write!(f, "Index file is damaged (checksum mismatch)"),
+            Error::Truncated => write!(f, "Index file is damaged (cut short)"),
+            Error::InvalidPattern(e) => write!(f, "Invalid exclude pattern: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        Error::Serialization(e.to_string())
+    }
+}
+
+impl From<notify::Error> for Error {
+    fn from(e: notify::Error) -> Self {
+        match e.kind {
+            notify::ErrorKind::Io(io) => Error::Io(io),
+            _ => Error::Watch(e.to_string()),
+        }
+    }
+}
+
+/// Sent to the UI as the message
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A path the crawler had to skip
+#[derive(Debug, Serialize)]
+pub struct CrawlError {
+    pub path: PathBuf,
+    pub error: Error,
+}
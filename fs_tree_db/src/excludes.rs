@@ -1,59 +1,371 @@
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
+
+use ignore::gitignore::{ Gitignore, GitignoreBuilder };
+use ignore::Match;
+use serde::{ Serialize, Deserialize };
+
+use crate::error::{ Error, Result };
+
+/// Directories end in `/` so a file with the same name is still indexed
 pub static DEFAULT_EXCLUDES: &[&str] = &[
     // Node.js / JS
-    "node_modules",
-    "dist",
-    "build",
-    ".parcel-cache",
-    ".turbo",
+    "node_modules/",
+    "dist/",
+    "build/",
+    ".parcel-cache/",
+    ".turbo/",
 
     // Rust
-    "target",
+    "target/",
 
     // Python
-    "__pycache__",
-    ".mypy_cache",
-    ".pytest_cache",
+    "__pycache__/",
+    ".mypy_cache/",
+    ".pytest_cache/",
 
     // Java
-    "bin",
-    "out",
+    "bin/",
+    "out/",
 
     // Git & VCS
-    ".git",
-    ".svn",
-    ".hg",
+    ".git/",
+    ".svn/",
+    ".hg/",
 
     // OS + IDE junk
     ".DS_Store",
-    ".idea",
-    ".vscode",
-    ".Trash",
-    ".history",
+    ".idea/",
+    ".vscode/",
+    ".Trash/",
+    ".history/",
     "thumbs.db",
 
     // Dependency managers
-    ".venv",
-    "env",
-    "venv",
-    ".env",
-    ".yarn",
-    ".pnpm-store",
+    ".venv/",
+    "env/",
+    "venv/",
+    ".env/",
+    ".yarn/",
+    ".pnpm-store/",
 
     // Build system
-    "cmake-build-debug",
-    "cmake-build-release",
-    ".gradle",
+    "cmake-build-debug/",
+    "cmake-build-release/",
+    ".gradle/",
 
     // Misc big/trash folders
-    "logs",
-    "cache",
-    "tmp",
-    "temp",
-    "coverage",
+    "logs/",
+    "cache/",
+    "tmp/",
+    "temp/",
+    "coverage/",
 ];
 
+/// Read from every directory when `ExcludeRules::ignore_files` is set, later ones win
+pub static IGNORE_FILES: &[&str] = &[".gitignore", ".ignore", ".fdignore"];
+
 pub fn get_excludes() -> Vec<String> {
     DEFAULT_EXCLUDES.iter()
         .map(|s| s.to_string())
         .collect()
 }
+
+/// What to leave out of an index, one `.gitignore` line per pattern.
+///
+/// `*.log` and `cache/` match at any depth, `!keep.log` brings a match back and a
+/// leading `/` anchors the pattern to the crawl root. Patterns starting with the crawl
+/// root itself are absolute paths, absolute paths outside the root never match.
+///
+/// These patterns win over anything found in ignore files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExcludeRules {
+    pub patterns: Vec<String>,
+
+    /// Honor `IGNORE_FILES` found while crawling, each one applies to its own subtree
+    pub ignore_files: bool,
+}
+
+impl From<Vec<String>> for ExcludeRules {
+    fn from(patterns: Vec<String>) -> Self {
+        ExcludeRules { patterns, ignore_files: false }
+    }
+}
+
+impl ExcludeRules {
+    pub fn defaults() -> Self {
+        get_excludes().into()
+    }
+
+    /// Checks the patterns and ties them to the directories being crawled.
+    /// Anchored and absolute patterns are resolved against each root on its own
+    pub fn compile(&self, roots: &[PathBuf]) -> Result<Excluder> {
+        let mut compiled = Vec::with_capacity(roots.len());
+
+        for root in roots {
+            let mut builder = GitignoreBuilder::new(root);
+
+            for pattern in &self.patterns {
+                if let Some(line) = anchor(pattern, root) {
+                    builder
+                        .add_line(None, &line)
+                        .map_err(|e| Error::InvalidPattern(e.to_string()))?;
+                }
+            }
+
+            let global = builder.build().map_err(|e| Error::InvalidPattern(e.to_string()))?;
+            compiled.push((root.clone(), global));
+        }
+
+        Ok(Excluder { roots: compiled, ignore_files: self.ignore_files })
+    }
+}
+
+/// Rewrites absolute paths under `root` into anchored patterns, `None` if it can't match
+fn anchor(pattern: &str, root: &Path) -> Option<String> {
+    let (negate, rest) = match pattern.strip_prefix('!') {
+        Some(rest) => ("!", rest),
+        None => ("", pattern),
+    };
+
+    let path = Path::new(rest);
+    if !path.is_absolute() {
+        return Some(pattern.to_string());
+    }
+
+    match path.strip_prefix(root) {
+        Ok(relative) => {
+            let segments: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+
+            if segments.is_empty() {
+                return None;
+            }
+
+            let dir_only = if rest.ends_with(['/', '\\']) { "/" } else { "" };
+            Some(format!("{}/{}{}", negate, segments.join("/"), dir_only))
+        }
+        // On Unix `/name` is also how a .gitignore anchors a pattern
+        Err(_) if cfg!(unix) && !root.starts_with(path) => Some(pattern.to_string()),
+        Err(_) => None,
+    }
+}
+
+/// Rules from the ignore files in one directory, on top of the ones above it
+#[derive(Debug)]
+pub struct DirIgnores {
+    matcher: Gitignore,
+    parent: Ignores,
+}
+
+/// Every ignore file that applies to a directory, closest first
+pub type Ignores = Option<Arc<DirIgnores>>;
+
+/// `ExcludeRules` compiled for a set of roots
+#[derive(Debug, Clone)]
+pub struct Excluder {
+    roots: Vec<(PathBuf, Gitignore)>,
+    ignore_files: bool,
+}
+
+impl Excluder {
+    pub fn roots(&self) -> impl Iterator<Item = &Path> {
+        self.roots.iter().map(|(root, _)| root.as_path())
+    }
+
+    /// The deepest root holding `path`
+    fn root_of(&self, path: &Path) -> Option<&(PathBuf, Gitignore)> {
+        self.roots
+            .iter()
+            .filter(|(root, _)| path.starts_with(root))
+            .max_by_key(|(root, _)| root.components().count())
+    }
+
+    /// `ignores` are the ones that apply to `path`'s parent
+    pub fn is_excluded(&self, path: &Path, is_dir: bool, ignores: &Ignores) -> bool {
+        if let Some((_, global)) = self.root_of(path) {
+            match global.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+
+        let mut node = ignores.as_deref();
+        while let Some(dir) = node {
+            match dir.matcher.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => node = dir.parent.as_deref(),
+            }
+        }
+
+        false
+    }
+
+    /// The ignores for `dir`'s children. `has_file` tells whether `dir` holds a file
+    /// by that name, crawlers already have the listing and can skip the stat
+    pub fn enter(&self, dir: &Path, parent: &Ignores, has_file: impl Fn(&str) -> bool) -> Ignores {
+        if !self.ignore_files {
+            return None;
+        }
+
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found = false;
+
+        for name in IGNORE_FILES.iter().filter(|name| has_file(name)) {
+            // A broken line only loses that line, same as git
+            let _ = builder.add(dir.join(name));
+            found = true;
+        }
+
+        match builder.build() {
+            Ok(matcher) if found && !matcher.is_empty() => {
+                Some(Arc::new(DirIgnores { matcher, parent: parent.clone() }))
+            }
+            _ => parent.clone(),
+        }
+    }
+
+    /// Reads the ignore files from the root down to `dir`, for checking a single
+    /// path without having crawled to it
+    pub fn ignores_for(&self, dir: &Path) -> Ignores {
+        let (root, _) = self.root_of(dir)?;
+        let relative = dir.strip_prefix(root).unwrap_or(Path::new(""));
+
+        let mut current = root.clone();
+        let mut ignores = self.enter(&current, &None, |name| current.join(name).is_file());
+
+        for part in relative.components() {
+            current.push(part);
+            ignores = self.enter(&current, &ignores, |name| current.join(name).is_file());
+        }
+
+        ignores
+    }
+
+    /// Same as `ignores_for` followed by `is_excluded`
+    pub fn is_excluded_fresh(&self, path: &Path, is_dir: bool) -> bool {
+        let ignores = match path.parent() {
+            Some(parent) if self.ignore_files => self.ignores_for(parent),
+            _ => None,
+        };
+
+        self.is_excluded(path, is_dir, &ignores)
+    }
+}
+
+/// Walks the disk applying the same rules as a crawl, yields files only.
+/// For searching before an index exists
+pub struct Walk {
+    excluder: Excluder,
+    stack: Vec<(std::fs::ReadDir, Ignores)>,
+}
+
+impl Walk {
+    /// Walks every root `excluder` was compiled for, in order
+    pub fn new(excluder: Excluder) -> Self {
+        let mut walk = Walk { stack: Vec::new(), excluder };
+        let roots: Vec<PathBuf> = walk.excluder.roots.iter().rev().map(|(root, _)| root.clone()).collect();
+        for root in roots {
+            walk.push(&root, &None);
+        }
+        walk
+    }
+
+    fn push(&mut self, dir: &Path, parent: &Ignores) {
+        if let Ok(entries) = std::fs::read_dir(dir) {
+            let ignores = self.excluder.enter(dir, parent, |name| dir.join(name).is_file());
+            self.stack.push((entries, ignores));
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        loop {
+            let (entries, ignores) = self.stack.last_mut()?;
+
+            let Some(entry) = entries.next() else {
+                self.stack.pop();
+                continue;
+            };
+            let Ok(entry) = entry else {
+                continue;
+            };
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+
+            let path = entry.path();
+            if self.excluder.is_excluded(&path, file_type.is_dir(), ignores) {
+                continue;
+            }
+
+            if file_type.is_file() {
+                return Some(path);
+            }
+
+            if file_type.is_dir() {
+                let ignores = ignores.clone();
+                self.push(&path, &ignores);
+            }
+        }
+    }
+}
+
+static DOCUMENT_EXTENSIONS: &[&str] = &[
+    "pdf", "doc", "docx", "odt", "rtf", "txt", "md",
+    "xls", "xlsx", "ods", "csv",
+    "ppt", "pptx", "odp",
+    "epub",
+];
+
+/// A named set of rules, saved with the index it built
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExcludeProfile {
+    pub name: String,
+    pub rules: ExcludeRules,
+}
+
+/// What new indexes get unless the user picked something else. Nothing is left out,
+/// folders like `bin` or `build` can be the user's own
+impl Default for ExcludeProfile {
+    fn default() -> Self {
+        ExcludeProfile::everything()
+    }
+}
+
+impl ExcludeProfile {
+    pub fn new(name: impl Into<String>, rules: impl Into<ExcludeRules>) -> Self {
+        ExcludeProfile { name: name.into(), rules: rules.into() }
+    }
+
+    /// Skips build output, dependencies and VCS folders, plus whatever ignore files say
+    pub fn developer() -> Self {
+        ExcludeProfile::new("developer", ExcludeRules { ignore_files: true, ..ExcludeRules::defaults() })
+    }
+
+    /// Indexes every file
+    pub fn everything() -> Self {
+        ExcludeProfile::new("everything", ExcludeRules::default())
+    }
+
+    /// Only office documents, text and PDFs, outside of hidden folders
+    pub fn documents() -> Self {
+        let mut patterns = vec!["*".to_string(), "!*/".to_string(), ".*/".to_string()];
+        patterns.extend(DOCUMENT_EXTENSIONS.iter().map(|ext| format!("!*.{}", ext)));
+
+        ExcludeProfile::new("documents only", patterns)
+    }
+
+    pub fn builtin() -> Vec<ExcludeProfile> {
+        // The default first, the app offers it first
+        vec![ExcludeProfile::everything(), ExcludeProfile::developer(), ExcludeProfile::documents()]
+    }
+}
@@ -0,0 +1,226 @@
+//! On-disk layout of a saved tree
+//!
+//! ```text
+//! [8]  MAGIC
+//! [4]  format version, little endian
+//! [4]  header length, little endian
+//! [..] Header (bincode)
+//! [..] Tree (bincode)
+//! ```
+
+use std::io::{ Read, Write };
+use std::path::{ Path, PathBuf };
+use std::time::SystemTime;
+
+use bincode::Options;
+use serde::{ Serialize, Deserialize };
+
+use crate::error::{ Error, Result };
+use crate::excludes::{ ExcludeProfile, ExcludeRules };
+use crate::Tree;
+
+pub const MAGIC: &[u8; 8] = b"FSTREEDB";
+
+/// Bump when `Header` or `Tree` change shape, and teach `decode_body` how to read the old one
+pub const FORMAT_VERSION: u32 = 2;
+
+const PREFIX_LEN: usize = MAGIC.len() + 4 + 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    /// When the tree was first built
+    pub created: SystemTime,
+    pub roots: Vec<PathBuf>,
+    pub files: u64,
+
+    /// CRC32 of the body
+    pub checksum: u32,
+
+    /// What was left out, rebuilds use the same
+    pub exclude: ExcludeProfile,
+}
+
+/// `Header` as v1 wrote it, before exclude profiles
+#[derive(Deserialize)]
+struct HeaderV1 {
+    created: SystemTime,
+    roots: Vec<PathBuf>,
+    files: u64,
+    checksum: u32,
+}
+
+impl From<HeaderV1> for Header {
+    fn from(v1: HeaderV1) -> Self {
+        Header {
+            created: v1.created,
+            roots: v1.roots,
+            files: v1.files,
+            checksum: v1.checksum,
+            exclude: legacy_profile(),
+        }
+    }
+}
+
+/// Every index saved before v2 was built with `DEFAULT_EXCLUDES`
+fn legacy_profile() -> ExcludeProfile {
+    ExcludeProfile::new("developer", ExcludeRules::defaults())
+}
+
+/// Same encoding `bincode::serialize` uses, but never reads past the input
+fn bincode_opts(limit: usize) -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_limit(limit as u64)
+}
+
+/// Writes to a temporary file first so a crash mid-write never leaves a half written index
+pub fn write(tree: &Tree, path: &Path) -> Result<()> {
+    let body = bincode::serialize(tree)?;
+
+    let header = Header {
+        created: tree.created(),
+        roots: tree.roots(),
+        files: tree.len() as u64,
+        checksum: crc32fast::hash(&body),
+        exclude: tree.profile().clone(),
+    };
+    let header = bincode::serialize(&header)?;
+
+    // The data dir doesn't exist until the first index is saved
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let tmp = path.with_extension("tmp");
+    {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&tmp)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        file.write_all(&(header.len() as u32).to_le_bytes())?;
+        file.write_all(&header)?;
+        file.write_all(&body)?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    }
+
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+pub fn read(path: &Path) -> Result<Tree> {
+    let bytes = std::fs::read(path)?;
+
+    if !bytes.starts_with(MAGIC) {
+        return decode_legacy(&bytes);
+    }
+
+    let (version, header, body) = split(&bytes)?;
+
+    if crc32fast::hash(body) != header.checksum {
+        return Err(Error::ChecksumMismatch);
+    }
+
+    decode_body(version, &header, body)
+}
+
+/// Reads only the header, without loading the tree
+pub fn read_header(path: &Path) -> Result<Header> {
+    read_start(path)?.map(|(_, header)| header).ok_or(Error::NotAnIndex)
+}
+
+/// The format version a tree was saved with, files from before versions were written are 0
+pub fn read_version(path: &Path) -> Result<u32> {
+    Ok(read_start(path)?.map_or(0, |(version, _)| version))
+}
+
+/// The version and header a saved tree starts with, reading nothing past them.
+/// `None` if the file doesn't start with `MAGIC`
+fn read_start(path: &Path) -> Result<Option<(u32, Header)>> {
+    let file = std::fs::File::open(path)?;
+
+    let mut prefix = Vec::new();
+    (&file).take(PREFIX_LEN as u64).read_to_end(&mut prefix)?;
+    if !prefix.starts_with(MAGIC) {
+        return Ok(None);
+    }
+    let (version, header_len) = read_prefix(&prefix)?;
+
+    // Not allocated up front, the length could be anything in a damaged file
+    let mut header = Vec::new();
+    file.take(header_len as u64).read_to_end(&mut header)?;
+    if header.len() < header_len {
+        return Err(Error::Truncated);
+    }
+
+    decode_header(version, &header).map(|header| Some((version, header)))
+}
+
+/// The format version and header length that follow `MAGIC`
+fn read_prefix(bytes: &[u8]) -> Result<(u32, usize)> {
+    if bytes.len() < PREFIX_LEN {
+        return Err(Error::Truncated);
+    }
+
+    let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    let header_len = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+
+    if version > FORMAT_VERSION {
+        return Err(Error::UnsupportedVersion { found: version, supported: FORMAT_VERSION });
+    }
+
+    Ok((version, header_len))
+}
+
+fn decode_header(version: u32, header: &[u8]) -> Result<Header> {
+    Ok(match version {
+        1 => bincode_opts(header.len()).deserialize::<HeaderV1>(header)?.into(),
+        _ => bincode_opts(header.len()).deserialize(header)?,
+    })
+}
+
+fn split(bytes: &[u8]) -> Result<(u32, Header, &[u8])> {
+    let (version, header_len) = read_prefix(bytes)?;
+
+    let rest = &bytes[PREFIX_LEN..];
+    if rest.len() < header_len {
+        return Err(Error::Truncated);
+    }
+
+    let (header, body) = rest.split_at(header_len);
+    Ok((version, decode_header(version, header)?, body))
+}
+
+/// Decodes the body as whatever `version` wrote, migrating it to the current `Tree`
+fn decode_body(version: u32, header: &Header, body: &[u8]) -> Result<Tree> {
+    match version {
+        // v2 only changed the header
+        1 | FORMAT_VERSION => {
+            let mut tree: Tree = bincode_opts(body.len()).deserialize(body)?;
+
+            if tree.len() as u64 != header.files {
+                return Err(Error::Serialization("file count doesn't match the header".into()));
+            }
+
+            tree.collect_free_slots();
+            tree.sort_children();
+            tree.set_profile(header.exclude.clone());
+            Ok(tree)
+        }
+        found => Err(Error::UnsupportedVersion { found, supported: FORMAT_VERSION }),
+    }
+}
+
+/// Trees saved before the header existed were a bare bincode `Vec<PathBuf>`.
+/// There's no metadata in them, filters will stat those files until the next rebuild
+fn decode_legacy(bytes: &[u8]) -> Result<Tree> {
+    let paths: Vec<PathBuf> = bincode_opts(bytes.len())
+        .deserialize(bytes)
+        .map_err(|_| Error::NotAnIndex)?;
+
+    if paths.is_empty() || !paths.iter().all(|p| p.is_absolute()) {
+        return Err(Error::NotAnIndex);
+    }
+
+    let mut tree = Tree::from_paths(paths);
+    tree.set_profile(legacy_profile());
+    Ok(tree)
+}
@@ -1,10 +1,505 @@
 pub mod tree;
 pub use tree::Tree;
 
+pub mod builder;
+pub use builder::{ CancelToken, Progress, TreeBuilder };
+
+pub mod options;
+pub use options::CrawlOptions;
+
+pub mod entry;
+pub use entry::{ Entry, FileKind, FileMeta };
+
 
 pub mod excludes;
+pub use excludes::{ ExcludeProfile, ExcludeRules };
+
+pub mod registry;
+pub use registry::IndexConfig;
+
+pub mod locations;
+pub use locations::SuggestedRoot;
+
+pub mod format;
+pub use format::Header;
+
+pub mod error;
+pub use error::{ CrawlError, Error, Result };
+
+pub mod watcher;
+pub use watcher::{ Change, SharedTree, TreeWatcher, WatchOptions };
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::{ Path, PathBuf };
+    use std::sync::{ Arc, RwLock };
+    use std::time::{ Duration, Instant };
+
+    /// Creates a fresh directory under the system temp dir for a test
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fs_tree_db_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn indexes_files_and_skips_excluded_dirs() {
+        let root = scratch_dir("index");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        std::fs::write(root.join("README.md"), "hi").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("node_modules/pkg/index.js"), "").unwrap();
+
+        let tree = Tree::new(root.clone(), excludes::get_excludes()).await.unwrap();
+
+        let mut found: Vec<PathBuf> = tree.iter().collect();
+        found.sort();
+        assert_eq!(found, vec![root.join("README.md"), root.join("src/main.rs")]);
+
+        let readme = tree.meta(&root.join("README.md")).unwrap();
+        assert_eq!(readme.size, 2);
+        assert_eq!(readme.kind, FileKind::File);
+        assert!(readme.modified.is_some());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn builds_report_progress_and_can_be_cancelled() {
+        let root = scratch_dir("progress");
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::write(root.join("a/one"), "").unwrap();
+        std::fs::write(root.join("a/b/two"), "").unwrap();
+
+        let builder = Tree::builder(&root);
+        let progress = builder.progress();
+        let tree = builder.build().await.unwrap();
+
+        let last = progress.borrow().clone();
+        assert!(last.finished);
+        assert_eq!(last.files_found, tree.len() as u64);
+        assert_eq!(last.dirs_visited, 3);
+        assert_eq!(last.queue_depth, 0);
+
+        // A single worker, or a tuned cap, still gets through everything
+        for options in [
+            CrawlOptions { workers: Some(1), ..Default::default() },
+            CrawlOptions { max_io: Some(1), auto_tune: true, ..Default::default() },
+        ] {
+            let tuned = Tree::builder(&root).options(options).build().await.unwrap();
+            assert_eq!(tuned.len(), tree.len());
+        }
+
+        let builder = Tree::builder(&root);
+        builder.cancel_token().cancel();
+        assert!(matches!(builder.build().await, Err(Error::Cancelled)));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn trees_hold_several_roots() {
+        let root = scratch_dir("roots");
+        for dir in ["docs/inner", "music"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::write(root.join("docs/inner/a.txt"), "").unwrap();
+        std::fs::write(root.join("music/b.mp3"), "").unwrap();
+        std::fs::write(root.join("skipped.txt"), "").unwrap();
+
+        // `docs/inner` is already covered by `docs`
+        let roots = [root.join("docs"), root.join("music"), root.join("docs/inner")];
+        let tree = TreeBuilder::with_roots(roots).build().await.unwrap();
+
+        assert_eq!(tree.roots(), vec![root.join("docs"), root.join("music")]);
+        let mut found: Vec<PathBuf> = tree.iter().collect();
+        found.sort();
+        assert_eq!(found, vec![root.join("docs/inner/a.txt"), root.join("music/b.mp3")]);
+
+        let tree: SharedTree = Arc::new(RwLock::new(tree));
+        let _watcher = TreeWatcher::new(tree.clone(), vec![]).unwrap();
+
+        std::fs::write(root.join("music/c.mp3"), "").unwrap();
+        assert!(wait_for(|| tree.read().unwrap().contains(&root.join("music/c.mp3"))));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn exclude_rules_match_like_gitignore() {
+        let root = scratch_dir("rules");
+        for dir in ["logs", "src/gen", "vendor/lib", "repo/out"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "a.log", "keep.log", "logs/x.txt", "src/main.rs", "src/gen/api.rs",
+            "vendor/lib/v.rs", "repo/out/o.bin", "repo/r.rs", "repo/r.tmp",
+        ] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        std::fs::write(root.join("repo/.gitignore"), "out/\n*.tmp\n").unwrap();
+
+        let rules = ExcludeRules {
+            patterns: vec![
+                "*.log".into(),
+                "!keep.log".into(),
+                "logs/".into(),
+                "/src/gen".into(),
+                root.join("vendor").display().to_string(),
+            ],
+            ignore_files: true,
+        };
+
+        let tree = Tree::builder(&root).exclude(rules.clone()).build().await.unwrap();
+        let mut found: Vec<PathBuf> = tree.iter().collect();
+        found.sort();
+
+        let expected: Vec<PathBuf> = ["keep.log", "repo/.gitignore", "repo/r.rs", "src/main.rs"]
+            .iter()
+            .map(|f| root.join(f))
+            .collect();
+        assert_eq!(found, expected);
+
+        // Searching without an index leaves out the same files
+        let mut walked: Vec<PathBuf> = excludes::Walk::new(rules.compile(std::slice::from_ref(&root)).unwrap()).collect();
+        walked.sort();
+        assert_eq!(walked, expected);
+
+        let bad = ExcludeRules::from(vec!["[z-a]".to_string()]);
+        let built = Tree::builder(&root).exclude(bad).build().await;
+        assert!(matches!(built, Err(Error::InvalidPattern(_))));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unreadable_dirs_are_reported() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = scratch_dir("errors");
+        std::fs::create_dir_all(root.join("locked")).unwrap();
+        std::fs::write(root.join("locked/secret.txt"), "").unwrap();
+        std::fs::write(root.join("open.txt"), "").unwrap();
+        std::fs::set_permissions(root.join("locked"), std::fs::Permissions::from_mode(0o000)).unwrap();
+
+        // Root ignores permissions, nothing to test there
+        let can_read = std::fs::read_dir(root.join("locked")).is_ok();
+
+        let tree = Tree::new(root.clone(), vec![]).await.unwrap();
+        std::fs::set_permissions(root.join("locked"), std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        if !can_read {
+            assert_eq!(tree.len(), 1);
+            let errors = tree.crawl_errors();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].path, root.join("locked"));
+            assert!(matches!(errors[0].error, Error::PermissionDenied(_)));
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_roots_and_bad_patterns_are_errors() {
+        let root = scratch_dir("missing").join("gone");
+
+        let tree = Tree::new(root.clone(), vec![]).await.unwrap();
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.crawl_errors().len(), 1);
+        assert_eq!(tree.crawl_errors()[0].path, root);
+
+        let built = Tree::new(root.clone(), vec!["[z-a]".to_string()]).await;
+        assert!(matches!(built, Err(Error::InvalidPattern(_))));
+
+        std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn first_run_suggests_home_then_drives() {
+        let roots = locations::suggested_roots();
+
+        assert_eq!(roots[0].path, locations::home_dir());
+        assert!(roots[0].recommended);
+        assert!(roots[1..].iter().all(|r| !r.recommended && r.path.is_absolute()));
+        assert!(!roots.iter().any(|r| r.path.starts_with("/proc") || r.path.starts_with("/boot")));
+
+        assert!(locations::index_registry().starts_with(locations::data_dir()));
+        assert_eq!(locations::expand_home(Path::new("~/docs")), locations::home_dir().join("docs"));
+    }
+
+    #[tokio::test]
+    async fn tree_moves_and_lists_nodes() {
+        let root = scratch_dir("nodes");
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::write(root.join("a/one.txt"), "1").unwrap();
+        std::fs::write(root.join("a/b/two.txt"), "2").unwrap();
+        std::fs::write(root.join("three.txt"), "3").unwrap();
+
+        let mut tree = Tree::new(root.clone(), vec![]).await.unwrap();
+        assert_eq!(tree.len(), 3);
+
+        let mut children = tree.children(&root.join("a"));
+        children.sort();
+        assert_eq!(children, vec![root.join("a/b"), root.join("a/one.txt")]);
+        assert_eq!(tree.under(&root.join("a")).count(), 2);
+
+        // Moving a directory carries everything inside along
+        assert_eq!(tree.rename(&root.join("a"), &root.join("z/a")), 2);
+        assert!(tree.contains(&root.join("z/a/b/two.txt")));
+        assert!(!tree.contains(&root.join("a/b/two.txt")));
+
+        assert_eq!(tree.remove(&root.join("z")), 2);
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![root.join("three.txt")]);
+
+        // Freed slots get reused
+        assert!(tree.insert(Entry { path: root.join("new/four.txt"), meta: None }));
+        assert_eq!(tree.len(), 2);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn children_are_found_whatever_order_they_came_in() {
+        let root = scratch_dir("sorted");
+        let mut tree = Tree::new(root.clone(), vec![]).await.unwrap();
+
+        for i in (0..500).rev().chain(500..1000) {
+            let dir = if i % 2 == 0 { "even" } else { "odd" };
+            assert!(tree.insert(Entry { path: root.join(format!("{}/f{}.txt", dir, i * 7919 % 1000)), meta: None }));
+        }
+        assert_eq!(tree.len(), 1000);
+        assert!(tree.contains(&root.join("even/f0.txt")));
+
+        // Renamed and removed children keep the rest findable
+        assert_eq!(tree.rename(&root.join("even/f0.txt"), &root.join("odd/zz.txt")), 1);
+        assert_eq!(tree.rename(&root.join("odd"), &root.join("a/odd")), 501);
+        assert_eq!(tree.remove(&root.join("a/odd/f7.txt")), 1);
+        assert!(tree.contains(&root.join("a/odd/zz.txt")));
+        assert!(!tree.contains(&root.join("a/odd/f7.txt")));
+        assert!((1..1000).filter(|i| i % 2 == 0).all(|i| tree.contains(&root.join(format!("even/f{}.txt", i * 7919 % 1000)))));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn renaming_onto_itself_changes_nothing() {
+        let root = scratch_dir("rename_same");
+        std::fs::create_dir_all(root.join("a")).unwrap();
+        std::fs::write(root.join("a/one.txt"), "1").unwrap();
+
+        // Like a case-only rename on a file system that ignores case
+        let mut tree = Tree::new(root.clone(), vec![]).await.unwrap();
+        assert_eq!(tree.rename(&root.join("a/one.txt"), &root.join("a/one.txt")), 0);
+        assert_eq!(tree.rename(&root.join("a"), &root.join("a")), 0);
+        assert!(tree.apply(&Change::Modified(root.join("a/one.txt"))));
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![root.join("a/one.txt")]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn batches_walk_like_entries_and_skip_removed_folders() {
+        let root = scratch_dir("batches");
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        for file in ["one.txt", "a/two.txt", "a/three.txt", "a/b/four.txt", "a/b/five.txt"] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+
+        let mut tree = Tree::new(root.clone(), vec![]).await.unwrap();
+        let walk = |tree: &Tree, max| {
+            let mut batches = tree.batches();
+            let mut paths = Vec::new();
+            loop {
+                let batch = batches.next_batch(tree, max);
+                if batch.is_empty() {
+                    return paths;
+                }
+                assert!(batch.len() <= max);
+                paths.extend(batch.into_iter().map(|(path, _)| path));
+            }
+        };
+        let entries: Vec<PathBuf> = tree.entries().map(|(path, _)| path).collect();
+        assert_eq!(walk(&tree, 2), entries);
+
+        // The tree can change between batches
+        let mut batches = tree.batches();
+        assert_eq!(batches.next_batch(&tree, 2).len(), 2);
+        tree.remove(&root.join("a/b"));
+        let rest = batches.next_batch(&tree, 10);
+        assert!(rest.iter().all(|(path, _)| !path.starts_with(root.join("a/b"))));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn saved_trees_are_checked_and_migrated() {
+        let root = scratch_dir("format");
+        std::fs::create_dir_all(root.join("a")).unwrap();
+        std::fs::write(root.join("a/one.txt"), "1").unwrap();
+        std::fs::write(root.join("two.txt"), "2").unwrap();
+
+        let tree = Tree::new(root.join("a"), vec![]).await.unwrap();
+        let save = root.join("tree.bincode");
+        tree.save(&save).unwrap();
+
+        let header = Tree::read_header(&save).unwrap();
+        assert_eq!(header.roots, vec![root.join("a")]);
+        assert_eq!(header.files, 1);
+        assert_eq!(header.exclude.name, "custom");
+
+        let loaded = Tree::load(&save).unwrap();
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), vec![root.join("a/one.txt")]);
+        assert_eq!(loaded.created(), tree.created());
+
+        // v1 headers had no profile, those trees were built with the default excludes
+        let v2 = std::fs::read(&save).unwrap();
+        let body = &v2[16 + u32::from_le_bytes(v2[12..16].try_into().unwrap()) as usize..];
+        let v1_header = (header.created, &header.roots, header.files, header.checksum);
+        let v1_header = bincode::serialize(&v1_header).unwrap();
+        let mut v1 = format::MAGIC.to_vec();
+        v1.extend(1u32.to_le_bytes());
+        v1.extend((v1_header.len() as u32).to_le_bytes());
+        v1.extend(v1_header);
+        v1.extend(body);
+        std::fs::write(&save, &v1).unwrap();
+        let from_v1 = Tree::load(&save).unwrap();
+        assert_eq!(from_v1.profile().rules, ExcludeRules::defaults());
+        assert_eq!(from_v1.len(), 1);
+        tree.save(&save).unwrap();
+
+        // Cut short mid-write
+        let bytes = std::fs::read(&save).unwrap();
+        std::fs::write(&save, &bytes[..bytes.len() - 3]).unwrap();
+        assert!(matches!(Tree::load(&save), Err(Error::ChecksumMismatch)));
+        std::fs::write(&save, &bytes[..20]).unwrap();
+        assert!(matches!(Tree::load(&save), Err(Error::Truncated)));
+        assert!(matches!(Tree::read_header(&save), Err(Error::Truncated)));
+
+        // Written by a newer build
+        let mut newer = bytes.clone();
+        newer[8..12].copy_from_slice(&(format::FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&save, &newer).unwrap();
+        assert!(matches!(Tree::load(&save), Err(Error::UnsupportedVersion { .. })));
+
+        std::fs::write(&save, b"definitely not a tree").unwrap();
+        assert!(matches!(Tree::load(&save), Err(Error::NotAnIndex)));
+
+        // Before the header existed trees were saved as a bare list of paths
+        let legacy = vec![root.join("a/one.txt"), root.join("two.txt")];
+        std::fs::write(&save, bincode::serialize(&legacy).unwrap()).unwrap();
+        let migrated = Tree::load(&save).unwrap();
+        assert_eq!(migrated.roots(), vec![root.clone()]);
+        assert!(migrated.contains(&root.join("a/one.txt")));
+        assert_eq!(migrated.len(), 2);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn save_paths_differ_for_names_that_sanitize_alike() {
+        let registry = Path::new("/data/indexes.json");
+        let paths: Vec<PathBuf> = ["a b", "a_b", "a/b", "Music", "music"]
+            .iter()
+            .map(|name| registry::save_path_for(registry, name))
+            .collect();
+
+        assert_eq!(paths[1], Path::new("/data/a_b.bincode"));
+        for (i, path) in paths.iter().enumerate() {
+            let lower = path.to_string_lossy().to_lowercase();
+            assert!(paths[i + 1..].iter().all(|other| other.to_string_lossy().to_lowercase() != lower), "{:?}", path);
+        }
+    }
+
+    /// Polls `cond` until it holds or a few seconds go by
+    fn wait_for(cond: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if cond() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn watcher_applies_create_rename_and_delete() {
+        let root = scratch_dir("watch");
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+
+        let tree: SharedTree = Arc::new(RwLock::new(Tree::new(root.clone(), vec![]).await.unwrap()));
+        let watcher = TreeWatcher::new(tree.clone(), vec![]).unwrap();
+        let mut changes = watcher.subscribe();
+
+        let has = |p: PathBuf| {
+            let tree = tree.clone();
+            move || tree.read().unwrap().contains(&p)
+        };
+
+        std::fs::write(root.join("docs/a.txt"), "a").unwrap();
+        assert!(wait_for(has(root.join("docs/a.txt"))));
+        assert_eq!(changes.recv().await.unwrap(), Change::Created(root.join("docs/a.txt")));
+
+        std::fs::rename(root.join("docs"), root.join("notes")).unwrap();
+        assert!(wait_for(has(root.join("notes/a.txt"))));
+
+        // The moved directory must still be watched under its new name
+        std::fs::remove_file(root.join("notes/a.txt")).unwrap();
+        assert!(wait_for(|| tree.read().unwrap().is_empty()));
+
+        drop(watcher);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn watcher_ignores_writes_to_excluded_files() {
+        let root = scratch_dir("watch_excluded");
+        std::fs::create_dir_all(root.join("target")).unwrap();
+
+        let tree: SharedTree = Arc::new(RwLock::new(Tree::new(root.clone(), vec![]).await.unwrap()));
+        let exclude = vec!["target/".to_string(), "*.log".to_string()];
+        let watcher = TreeWatcher::new(tree.clone(), exclude).unwrap();
+
+        // Creating and then writing them sends both a create and a modify event
+        std::fs::write(root.join("debug.log"), "a").unwrap();
+        std::fs::write(root.join("debug.log"), "ab").unwrap();
+        std::fs::write(root.join("target/out.o"), "a").unwrap();
+        std::fs::write(root.join("target/out.o"), "ab").unwrap();
+
+        // Events come in order, once this one is in the others were handled
+        std::fs::write(root.join("done.txt"), "").unwrap();
+        assert!(wait_for(|| tree.read().unwrap().contains(&root.join("done.txt"))));
+        assert_eq!(tree.read().unwrap().len(), 1);
+
+        drop(watcher);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn watcher_polls_when_out_of_watches() {
+        let root = scratch_dir("poll");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+
+        let tree: SharedTree = Arc::new(RwLock::new(Tree::new(root.clone(), vec![]).await.unwrap()));
+        let options = WatchOptions {
+            rescan_interval: Duration::from_millis(50),
+            max_watches: Some(0),
+        };
+        let watcher = TreeWatcher::with_options(tree.clone(), vec![], options).unwrap();
+        assert_eq!(watcher.polled_dirs(), 2);
+
+        std::fs::write(root.join("sub/b.txt"), "b").unwrap();
+        assert!(wait_for(|| tree.read().unwrap().len() == 1));
+
+        std::fs::remove_dir_all(root.join("sub")).unwrap();
+        assert!(wait_for(|| tree.read().unwrap().is_empty()));
+        assert!(wait_for(|| watcher.polled_dirs() == 1));
+
+        drop(watcher);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
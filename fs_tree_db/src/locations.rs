@@ -0,0 +1,169 @@
+use std::path::{ Path, PathBuf };
+
+use serde::Serialize;
+
+/// Same as the Tauri app's identifier, so the app and the examples share their files
+pub const APP_ID: &str = "com.file-explorer.app";
+
+/// Saved indexes and the list of them. Indexes are big and tied to this machine, so
+/// they go in the local data dir: `~/.local/share` on Linux, `%LOCALAPPDATA%` on Windows
+pub fn data_dir() -> PathBuf {
+    dirs::data_local_dir().unwrap_or_else(std::env::temp_dir).join(APP_ID)
+}
+
+/// User settings: `~/.config` on Linux, `%APPDATA%` on Windows
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir().unwrap_or_else(std::env::temp_dir).join(APP_ID)
+}
+
+pub fn indexes_dir() -> PathBuf {
+    data_dir().join("indexes")
+}
+
+/// Lists every index with its roots, profile and save file
+pub fn index_registry() -> PathBuf {
+    indexes_dir().join("indexes.json")
+}
+
+pub fn config_file() -> PathBuf {
+    config_dir().join("config.json")
+}
+
+/// Where relative paths start from when nothing else was given, `/` if there's no home
+pub fn home_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or_else(|| PathBuf::from(std::path::MAIN_SEPARATOR_STR))
+}
+
+/// A folder worth offering as an index root on first run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SuggestedRoot {
+    pub path: PathBuf,
+
+    /// What to show instead of the bare path, like "Home" or "Drive D:"
+    pub label: String,
+
+    /// Pre-selected, only the home folder is. Whole drives take a while to index
+    pub recommended: bool,
+}
+
+/// The home folder followed by every drive or mounted filesystem that holds user files
+pub fn suggested_roots() -> Vec<SuggestedRoot> {
+    let home = home_dir();
+    let mut roots = vec![SuggestedRoot { path: home.clone(), label: "Home".to_string(), recommended: true }];
+
+    for (path, label) in mounts() {
+        if path != home && !roots.iter().any(|r| r.path == path) {
+            roots.push(SuggestedRoot { path, label, recommended: false });
+        }
+    }
+
+    roots
+}
+
+/// Filesystems that hold files people care about, anything else is virtual or a system volume
+#[cfg(target_os = "linux")]
+static DISK_FILESYSTEMS: &[&str] = &[
+    "ext2", "ext3", "ext4", "btrfs", "xfs", "zfs", "f2fs", "bcachefs", "jfs", "reiserfs",
+    "ntfs", "ntfs3", "fuseblk", "vfat", "exfat", "hfsplus",
+    "nfs", "nfs4", "cifs", "smb3",
+];
+
+/// Mount points under these are system or container internals
+#[cfg(target_os = "linux")]
+static SYSTEM_MOUNTS: &[&str] = &["/boot", "/efi", "/snap", "/var", "/usr", "/opt", "/nix", "/proc", "/sys"];
+
+#[cfg(target_os = "linux")]
+fn mounts() -> Vec<(PathBuf, String)> {
+    let Ok(table) = std::fs::read_to_string("/proc/self/mounts") else {
+        return vec![(PathBuf::from("/"), "/".to_string())];
+    };
+
+    table
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_device, mount_point, fs) = (fields.next()?, fields.next()?, fields.next()?);
+
+            if !DISK_FILESYSTEMS.contains(&fs) {
+                return None;
+            }
+
+            let path = PathBuf::from(unescape_mount(mount_point));
+            if SYSTEM_MOUNTS.iter().any(|system| path.starts_with(system)) {
+                return None;
+            }
+
+            Some((path.clone(), path.display().to_string()))
+        })
+        .collect()
+}
+
+/// `/proc/self/mounts` writes spaces, tabs and backslashes in paths as `\040`, `\011` and `\134`
+#[cfg(target_os = "linux")]
+fn unescape_mount(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        let code = rest.get(i + 1..i + 4).and_then(|oct| u8::from_str_radix(oct, 8).ok());
+
+        match code {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[i + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(target_os = "macos")]
+fn mounts() -> Vec<(PathBuf, String)> {
+    let mut found = vec![(PathBuf::from("/"), "Macintosh HD".to_string())];
+
+    // Every other disk shows up here, including the system one linked back to `/`
+    if let Ok(volumes) = std::fs::read_dir("/Volumes") {
+        for volume in volumes.flatten() {
+            let path = volume.path();
+            let is_link = volume.file_type().is_ok_and(|t| t.is_symlink());
+
+            if !is_link {
+                found.push((path, volume.file_name().to_string_lossy().to_string()));
+            }
+        }
+    }
+
+    found
+}
+
+#[cfg(windows)]
+fn mounts() -> Vec<(PathBuf, String)> {
+    (b'A'..=b'Z')
+        .map(|letter| format!("{}:\\", letter as char))
+        .filter(|drive| Path::new(drive).exists())
+        .map(|drive| {
+            let label = format!("Drive {}", drive.trim_end_matches('\\'));
+            (PathBuf::from(drive), label)
+        })
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn mounts() -> Vec<(PathBuf, String)> {
+    vec![(PathBuf::from("/"), "/".to_string())]
+}
+
+/// Turns `~/...` into a path under the home folder, for roots typed by hand
+pub fn expand_home(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => home_dir().join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{ Serialize, Deserialize };
+
+/// Crawl threads per core when `workers` isn't set. Listing a directory mostly
+/// waits on the disk, so a few more threads than cores keeps it busy
+const THREADS_PER_CORE: usize = 2;
+
+/// How hard a build hits the disk.
+///
+/// SSDs and NVMe drives keep getting faster up to dozens of directories listed at once,
+/// spinning disks slow down past a few because the head has to seek between them.
+/// Leave everything unset to get `THREADS_PER_CORE` workers with no cap.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrawlOptions {
+    /// Crawl threads, defaults to twice the core count
+    pub workers: Option<usize>,
+
+    /// Most directories being listed at once, defaults to `workers`.
+    /// 1 to 4 is a good start for spinning disks
+    pub max_io: Option<usize>,
+
+    /// Start from a few directories at a time and move towards whatever lists
+    /// the most directories per second, never above `max_io`
+    pub auto_tune: bool,
+}
+
+impl CrawlOptions {
+    pub fn workers(&self) -> usize {
+        self.workers
+            .filter(|&n| n > 0)
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|n| n.get() * THREADS_PER_CORE)
+                    .unwrap_or(8)
+            })
+    }
+
+    /// Never more than there are workers to do the listing
+    pub fn max_io(&self) -> usize {
+        let workers = self.workers();
+        self.max_io.filter(|&n| n > 0).map_or(workers, |n| n.min(workers))
+    }
+}
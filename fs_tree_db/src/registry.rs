@@ -0,0 +1,85 @@
+//! The list of named indexes, shared by the app and the `fstree` CLI
+
+use std::path::{ Path, PathBuf };
+
+use serde::{ Serialize, Deserialize };
+
+use crate::error::{ Error, Result };
+use crate::excludes::ExcludeProfile;
+
+/// A named index and where it's saved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexConfig {
+    pub name: String,
+    pub roots: Vec<PathBuf>,
+
+    /// What the next build leaves out. Loading a saved tree replaces it with the one in its header
+    pub profile: ExcludeProfile,
+    pub save_path: PathBuf,
+}
+
+impl IndexConfig {
+    pub fn new(name: impl Into<String>, roots: Vec<PathBuf>, profile: ExcludeProfile, save_path: &Path) -> Self {
+        IndexConfig {
+            name: name.into(),
+            roots: outermost_roots(roots),
+            profile,
+            save_path: save_path.to_path_buf(),
+        }
+    }
+}
+
+/// Reads the registry at `path`, empty if there's none yet or it can't be read
+pub fn read(path: &Path) -> Vec<IndexConfig> {
+    std::fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+pub fn write(path: &Path, indexes: &[IndexConfig]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let json = serde_json::to_vec_pretty(indexes).map_err(|e| Error::Serialization(e.to_string()))?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+/// Where an index called `name` gets saved, next to the registry at `registry`.
+/// Names that had to be changed to make a file name, like `a b` or `Music`, get a hash
+/// of the real name added, so `a b` and `a_b` don't share a file, or `Music` and `music`
+/// on file systems that ignore case
+pub fn save_path_for(registry: &Path, name: &str) -> PathBuf {
+    let file: String = name
+        .chars()
+        .map(|c| if c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+
+    if file == name {
+        registry.with_file_name(format!("{}.bincode", file))
+    } else {
+        registry.with_file_name(format!("{}-{:08x}.bincode", file, fnv1a(name)))
+    }
+}
+
+/// Stays the same between builds, unlike `DefaultHasher`, since the path is worked out again later
+fn fnv1a(text: &str) -> u32 {
+    text.bytes().fold(0x811c9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193))
+}
+
+/// Drops roots that sit inside another root, their files are found through the outer one
+pub fn outermost_roots(roots: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let mut kept: Vec<PathBuf> = Vec::new();
+
+    for root in roots {
+        if kept.iter().any(|r| root.starts_with(r)) {
+            continue;
+        }
+        kept.retain(|r| !r.starts_with(&root));
+        kept.push(root);
+    }
+
+    kept
+}
@@ -1,142 +1,750 @@
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use std::path::PathBuf;
-use std::collections::VecDeque;
+use std::ffi::{ OsStr, OsString };
+use std::path::{ Component, Path, PathBuf };
+use std::time::SystemTime;
 use serde::{ Serialize, Deserialize };
 
+use crate::builder::Listing;
+use crate::excludes::ExcludeProfile;
+use crate::entry::{ Entry, FileMeta };
+use crate::error::{ CrawlError, Result };
+use crate::format::{ self, Header };
+use crate::watcher::Change;
+
+pub(crate) type DirId = u32;
+type FileId = u32;
+
 #[derive(Serialize, Deserialize)]
-pub struct Tree {
-    // Holds all paths files found
-    data: Vec<PathBuf>,
+struct Dir {
+    // Last path segment, roots hold their full path
+    name: OsString,
+    parent: Option<DirId>,
+
+    // Sorted by name, so a child is found with a binary search
+    dirs: Vec<DirId>,
+    files: Vec<FileId>,
 }
 
-type SharedQueue = Arc<Mutex<VecDeque<PathBuf>>>;
-type SharedData = Arc<Mutex<Vec<PathBuf>>>;
+#[derive(Serialize, Deserialize)]
+struct File {
+    name: OsString,
+    parent: DirId,
+    meta: Option<FileMeta>,
+}
 
-const THREAD_MULTIPLIER: usize = {
-    #[cfg(feature = "low")]
-    { 64 }
+/// Files are stored as name segments hanging from directory nodes, so shared
+/// prefixes are stored once. Full paths are rebuilt when iterating.
+///
+/// Indexing `/usr` (86,822 files, default excludes) with
+/// `cargo run --release --example layout -- /usr`, against a flat `Vec<Entry>` of full paths:
+///
+/// | layout | memory  | on disk |
+/// |--------|---------|---------|
+/// | flat   | 20.1 MB | 11.0 MB |
+/// | tree   | 15.7 MB |  8.6 MB |
+///
+/// Run `cargo run --release --example layout -- <path>` for your own numbers.
+#[derive(Serialize, Deserialize)]
+pub struct Tree {
+    created: SystemTime,
+    roots: Vec<DirId>,
 
-    #[cfg(all(not(feature = "low"), feature = "medium"))]
-    { 128 }
+    // Removed nodes leave a `None` behind so ids stay stable, the slot is reused later
+    dirs: Vec<Option<Dir>>,
+    files: Vec<Option<File>>,
 
-    #[cfg(all(not(feature = "low"), not(feature = "medium"), feature = "high"))]
-    { 512 }
+    // Number of files
+    len: usize,
 
-    #[cfg(all(not(feature = "low"), not(feature = "medium"), not(feature = "high"), feature = "peak"))]
-    { 1024 }
+    #[serde(skip)]
+    free_dirs: Vec<DirId>,
+    #[serde(skip)]
+    free_files: Vec<FileId>,
 
-    #[cfg(not(any(feature = "low", feature = "medium", feature = "high", feature = "peak")))]
-    { 32 } // fallback
-};
+    // Paths skipped while building, not saved
+    #[serde(skip)]
+    errors: Vec<CrawlError>,
 
+    // Lives in the file header
+    #[serde(skip)]
+    profile: ExcludeProfile,
+}
 
 impl Tree {
-    pub async fn new(path: PathBuf, lazy_exclude: Vec<String>) -> Self {
+    /// Crawls `path`, see `Tree::builder` to follow progress or cancel.
+    /// Fails if one of the exclude patterns is invalid
+    pub async fn new(path: PathBuf, lazy_exclude: Vec<String>) -> Result<Self> {
+        Tree::builder(path)
+            .exclude(lazy_exclude)
+            .build()
+            .await
+    }
+
+    /// Turns what the workers found into directory and file nodes
+    pub(crate) fn assemble(
+        roots: Vec<PathBuf>,
+        dir_count: u32,
+        listings: Vec<Listing>,
+        errors: Vec<CrawlError>
+    ) -> Self {
+        let mut tree = Tree::with_roots(roots);
+        tree.errors = errors;
+        tree.dirs.resize_with(dir_count as usize, || None);
+
+        // Every directory is named by its parent's listing, create them all before linking
+        for listing in &listings {
+            for (id, name) in &listing.subdirs {
+                tree.dirs[*id as usize] = Some(Dir::new(name.clone(), Some(listing.dir)));
+            }
+        }
 
-        let data: SharedData = Arc::new(Mutex::new(Vec::new()));
-        let queue: SharedQueue = Arc::new(Mutex::new(VecDeque::new()));
-        let exclude = Arc::new(lazy_exclude);
+        for listing in listings {
+            let dir = listing.dir;
+            let children: Vec<DirId> = listing.subdirs.iter().map(|(id, _)| *id).collect();
+            let mut files = Vec::with_capacity(listing.files.len());
 
-        queue.lock().await.push_back(path.clone());
+            for (name, meta) in listing.files {
+                files.push(tree.files.len() as FileId);
+                tree.files.push(Some(File { name, parent: dir, meta }));
+            }
 
-        let mut tasks = Vec::new();
+            tree.len += files.len();
+            let node = tree.dir_mut(dir);
+            node.dirs = children;
+            node.files = files;
+        }
 
-        let num_workers = std::thread::available_parallelism()
-            .map(|n| n.get() * THREAD_MULTIPLIER) // ← try 8x core count
-            .unwrap_or(32);       // safety net
+        tree.sort_children();
+        tree
+    }
 
-        for _ in 0..num_workers {
-            let q = queue.clone();
-            let d = data.clone();
-            let e = exclude.clone();
+    fn with_root(root: PathBuf) -> Self {
+        Tree::with_roots(vec![root])
+    }
 
-            tasks.push(
-                tokio::spawn(async move {
-                    Tree::worker_loop(q, d, e).await;
-                })
-            );
+    /// Roots take the first dir ids, in order
+    fn with_roots(roots: Vec<PathBuf>) -> Self {
+        Tree {
+            created: SystemTime::now(),
+            roots: (0..roots.len() as DirId).collect(),
+            dirs: roots.into_iter().map(|root| Some(Dir::new(root.into_os_string(), None))).collect(),
+            files: Vec::new(),
+            len: 0,
+            free_dirs: Vec::new(),
+            free_files: Vec::new(),
+            errors: Vec::new(),
+            profile: ExcludeProfile::default(),
         }
+    }
+
+    /// Builds a tree out of bare paths, rooted at the deepest directory they all share
+    pub(crate) fn from_paths(paths: Vec<PathBuf>) -> Self {
+        let mut root = paths
+            .first()
+            .and_then(|p| p.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
 
-        for t in tasks {
-            t.await.unwrap();
+        for path in &paths {
+            while !path.starts_with(&root) {
+                root = root.parent().map(Path::to_path_buf).unwrap_or_default();
+            }
         }
 
-        let data_final = Arc::try_unwrap(data).unwrap().into_inner();
+        let mut tree = Tree::with_root(root);
 
-        Tree { data: data_final }
+        for path in paths {
+            let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                continue;
+            };
+
+            // Paths are known to be unique, skip the duplicate check `insert` does
+            if let Some(dir) = tree.ensure_dir(parent) {
+                let id = tree.alloc_file(File { name: name.to_os_string(), parent: dir, meta: None });
+                tree.link_file(dir, id);
+                tree.len += 1;
+            }
+        }
+
+        tree
     }
 
-    async fn worker_loop(queue: SharedQueue, data: SharedData, exclude: Arc<Vec<String>>) {
-        loop {
-            let next_path = {
-                let mut q = queue.lock().await;
-                q.pop_front()
-            };
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// When the tree was first built
+    pub fn created(&self) -> SystemTime {
+        self.created
+    }
+
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.roots
+            .iter()
+            .map(|id| PathBuf::from(&self.dir(*id).name))
+            .collect()
+    }
+
+    /// Paths that couldn't be read while building, and why.
+    /// Empty for trees loaded from disk
+    pub fn crawl_errors(&self) -> &[CrawlError] {
+        &self.errors
+    }
+
+    /// The exclude rules the tree was built with
+    pub fn profile(&self) -> &ExcludeProfile {
+        &self.profile
+    }
+
+    pub(crate) fn set_profile(&mut self, profile: ExcludeProfile) {
+        self.profile = profile;
+    }
 
-            let Some(path) = next_path else {
-                break;
+    pub fn save(&self, path: &Path) -> Result<()> {
+        format::write(self, path)
+    }
+
+    /// Loads a saved tree, migrating it if it was saved by an older version.
+    /// Anything that can't be read or migrated is an error, the caller should rebuild
+    pub fn load(path: &Path) -> Result<Self> {
+        format::read(path)
+    }
+
+    /// Reads what's in a saved tree without loading it
+    pub fn read_header(path: &Path) -> Result<Header> {
+        format::read_header(path)
+    }
+
+    /// The format version a saved tree was written with, see `format::FORMAT_VERSION`
+    pub fn format_version(path: &Path) -> Result<u32> {
+        format::read_version(path)
+    }
+
+    /// Keeps children in name order, trees saved before that weren't
+    pub(crate) fn sort_children(&mut self) {
+        for id in 0..self.dirs.len() {
+            let Some(dir) = self.dirs[id].as_mut() else {
+                continue;
             };
-            let _ = Tree::process_path(&path, &queue, &data, &exclude).await;
+            let mut dirs = std::mem::take(&mut dir.dirs);
+            let mut files = std::mem::take(&mut dir.files);
+
+            dirs.sort_by(|a, b| self.dir(*a).name.cmp(&self.dir(*b).name));
+            files.sort_by(|a, b| self.file(*a).name.cmp(&self.file(*b).name));
+
+            let dir = self.dir_mut(id as DirId);
+            dir.dirs = dirs;
+            dir.files = files;
+        }
+    }
+
+    /// Free slots aren't saved, find them again after loading
+    pub(crate) fn collect_free_slots(&mut self) {
+        self.free_dirs = (0..self.dirs.len() as DirId)
+            .filter(|id| self.dirs[*id as usize].is_none())
+            .collect();
+        self.free_files = (0..self.files.len() as FileId)
+            .filter(|id| self.files[*id as usize].is_none())
+            .collect();
+    }
+
+    pub fn get_data(&self) -> Vec<PathBuf> {
+        self.iter().collect()
+    }
+
+    /// Iterates over every indexed file path
+    pub fn iter(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.entries().map(|(path, _)| path)
+    }
+
+    /// Iterates over every indexed file along with its cached metadata
+    pub fn entries(&self) -> Entries<'_> {
+        let stack = self.roots
+            .iter()
+            .rev()
+            .map(|id| (*id, PathBuf::from(&self.dir(*id).name)))
+            .collect();
+
+        Entries { tree: self, stack, current: None }
+    }
+
+    /// Every file like `entries`, a batch at a time, see `Batches`
+    pub fn batches(&self) -> Batches {
+        let stack = self.roots
+            .iter()
+            .rev()
+            .map(|id| PathBuf::from(&self.dir(*id).name))
+            .collect();
+
+        Batches { stack, current: None }
+    }
+
+    /// Every file under `dir`, at any depth. Empty if `dir` isn't in the tree
+    pub fn under(&self, dir: &Path) -> Entries<'_> {
+        let stack = match self.find_dir(dir) {
+            Some(id) => vec![(id, dir.to_path_buf())],
+            None => Vec::new(),
+        };
+
+        Entries { tree: self, stack, current: None }
+    }
+
+    /// Files and directories directly inside `dir`
+    pub fn children(&self, dir: &Path) -> Vec<PathBuf> {
+        let Some(id) = self.find_dir(dir) else {
+            return Vec::new();
+        };
+        let node = self.dir(id);
+
+        node.dirs
+            .iter()
+            .map(|d| dir.join(&self.dir(*d).name))
+            .chain(node.files.iter().map(|f| dir.join(&self.file(*f).name)))
+            .collect()
+    }
+
+    /// Files directly inside `dir` along with their cached metadata
+    pub fn files_in(&self, dir: &Path) -> Vec<(PathBuf, Option<&FileMeta>)> {
+        let Some(id) = self.find_dir(dir) else {
+            return Vec::new();
+        };
+
+        self.dir(id).files
+            .iter()
+            .map(|f| {
+                let file = self.file(*f);
+                (dir.join(&file.name), file.meta.as_ref())
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.find_file(path).is_some()
+    }
+
+    /// Cached metadata for a file, `None` if it isn't indexed or couldn't be stat'ed
+    pub fn meta(&self, path: &Path) -> Option<&FileMeta> {
+        self.find_file(path).and_then(|id| self.file(id).meta.as_ref())
+    }
+
+    /// Adds a file to the tree, returns false if it was already there.
+    /// An existing entry gets its metadata replaced
+    pub fn insert(&mut self, entry: Entry) -> bool {
+        let (Some(parent), Some(name)) = (entry.path.parent(), entry.path.file_name()) else {
+            return false;
+        };
+
+        let Some(dir) = self.ensure_dir(parent) else {
+            return false;
+        };
+
+        if let Some(id) = self.file_in(dir, name) {
+            self.file_mut(id).meta = entry.meta;
+            return false;
         }
+
+        let id = self.alloc_file(File { name: name.to_os_string(), parent: dir, meta: entry.meta });
+        self.link_file(dir, id);
+        self.len += 1;
+        true
     }
 
-    async fn process_path(
-        path: &PathBuf,
-        queue: &SharedQueue,
-        data: &SharedData,
-        exclude: &Arc<Vec<String>>
-    ) -> Result<(), String> {
-        if !path.exists() {
-            return Ok(());
+    /// Removes a file, or every file under a directory. Returns how many were removed
+    pub fn remove(&mut self, path: &Path) -> usize {
+        if let Some(id) = self.find_file(path) {
+            self.free_file(id);
+            return 1;
         }
 
-        let entries = match tokio::fs::read_dir(path).await {
-            Ok(dir) => dir,
-            Err(_) => {
-                return Ok(());
+        let Some(id) = self.find_dir(path) else {
+            return 0;
+        };
+
+        // Roots stay, only what's inside them goes
+        if self.roots.contains(&id) {
+            let node = self.dir_mut(id);
+            let dirs = std::mem::take(&mut node.dirs);
+            let files = std::mem::take(&mut node.files);
+            let before = self.len;
+
+            for f in files {
+                self.free_file(f);
             }
+            for d in dirs {
+                self.free_dir(d);
+            }
+
+            return before - self.len;
+        }
+
+        let before = self.len;
+        self.free_dir(id);
+        before - self.len
+    }
+
+    /// Moves a file, or every file under a directory, to a new path. Returns how many moved
+    pub fn rename(&mut self, from: &Path, to: &Path) -> usize {
+        let (Some(to_parent), Some(to_name)) = (to.parent(), to.file_name()) else {
+            return 0;
         };
 
-        let mut dir = entries;
-        while let Ok(Some(entry)) = dir.next_entry().await {
-            let file_type = entry.file_type().await.unwrap();
-            let entry_path = entry.path();
+        if let Some(id) = self.find_file(from) {
+            // Nothing moves, and removing `to` would free the file being moved
+            if self.find_file(to) == Some(id) {
+                return 0;
+            }
+
+            // Renaming over an existing file replaces it
+            self.remove(to);
+
+            let Some(new_parent) = self.ensure_dir(to_parent) else {
+                self.free_file(id);
+                return 0;
+            };
+
+            self.unlink_file(id);
+            let file = self.file_mut(id);
+            file.parent = new_parent;
+            file.name = to_name.to_os_string();
+            self.link_file(new_parent, id);
+            return 1;
+        }
 
-            if file_type.is_file() {
-                // dbg!(&entry_path);
-                data.lock().await.push(entry_path);
-            } else if file_type.is_dir() {
-                let name = entry.file_name();
-                let name_str = name.to_string_lossy();
+        let Some(id) = self.find_dir(from) else {
+            return 0;
+        };
+
+        if self.roots.contains(&id) || to.starts_with(from) || self.find_dir(to) == Some(id) {
+            return 0;
+        }
+
+        self.remove(to);
+
+        let Some(new_parent) = self.ensure_dir(to_parent) else {
+            let before = self.len;
+            self.free_dir(id);
+            return before - self.len;
+        };
 
-                if exclude.iter().any(|e| e == &name_str) {
-                    continue;
+        self.detach_dir(id);
+        let dir = self.dir_mut(id);
+        dir.parent = Some(new_parent);
+        dir.name = to_name.to_os_string();
+        self.link_dir(new_parent, id);
+
+        self.count_files(id)
+    }
+
+    /// Applies a change reported by the watcher, returns false if it didn't change anything
+    pub fn apply(&mut self, change: &Change) -> bool {
+        match change {
+            Change::Created(path) | Change::Modified(path) => {
+                let entry = Entry::read(path.clone());
+
+                // Already gone again, a removal will follow
+                if entry.meta.is_none() {
+                    return false;
                 }
 
-                queue.lock().await.push_back(entry_path);
+                self.insert(entry) || matches!(change, Change::Modified(_))
             }
+            Change::Removed(path) => self.remove(path) > 0,
+            Change::Renamed { from, to } => self.rename(from, to) > 0,
         }
+    }
+
+    /// Rough number of bytes the tree keeps on the heap
+    pub fn heap_size(&self) -> usize {
+        let dirs: usize = self.dirs
+            .iter()
+            .flatten()
+            .map(|d| {
+                d.name.capacity() + (d.dirs.capacity() + d.files.capacity()) * size_of::<u32>()
+            })
+            .sum();
+
+        let files: usize = self.files
+            .iter()
+            .flatten()
+            .map(|f| f.name.capacity())
+            .sum();
 
-        Ok(())
+        dirs +
+            files +
+            self.dirs.capacity() * size_of::<Option<Dir>>() +
+            self.files.capacity() * size_of::<Option<File>>() +
+            (self.roots.capacity() + self.free_dirs.capacity() + self.free_files.capacity()) *
+                size_of::<u32>()
     }
 
-    pub fn len(&self) -> usize {
-        return self.data.len();
+    fn dir(&self, id: DirId) -> &Dir {
+        self.dirs[id as usize].as_ref().expect("Dangling dir id")
     }
 
-    pub fn save(&self, path: &PathBuf) -> std::io::Result<()> {
-        let bytes = bincode::serialize(self).unwrap();
-        std::fs::write(path, bytes)
+    fn dir_mut(&mut self, id: DirId) -> &mut Dir {
+        self.dirs[id as usize].as_mut().expect("Dangling dir id")
     }
 
-    pub fn load(path: &PathBuf) -> std::io::Result<Self> {
-        let bytes = std::fs::read(path)?;
-        let tree: Tree = bincode::deserialize(&bytes).unwrap();
-        Ok(tree)
+    fn file(&self, id: FileId) -> &File {
+        self.files[id as usize].as_ref().expect("Dangling file id")
     }
 
-    pub fn get_data(&self) -> Vec<PathBuf> {
-        self.data.clone()
+    fn file_mut(&mut self, id: FileId) -> &mut File {
+        self.files[id as usize].as_mut().expect("Dangling file id")
+    }
+
+    /// Finds which root `path` is under and what's left of it after the root
+    fn split_root<'p>(&self, path: &'p Path) -> Option<(DirId, &'p Path)> {
+        self.roots.iter().find_map(|id| {
+            path.strip_prefix(&self.dir(*id).name)
+                .ok()
+                .map(|rest| (*id, rest))
+        })
+    }
+
+    fn find_dir(&self, path: &Path) -> Option<DirId> {
+        let (mut id, rest) = self.split_root(path)?;
+
+        for component in rest.components() {
+            let Component::Normal(name) = component else {
+                return None;
+            };
+
+            id = self.dir(id).dirs[self.dir_slot(id, name).ok()?];
+        }
+
+        Some(id)
+    }
+
+    fn find_file(&self, path: &Path) -> Option<FileId> {
+        let dir = self.find_dir(path.parent()?)?;
+        self.file_in(dir, path.file_name()?)
+    }
+
+    fn file_in(&self, dir: DirId, name: &OsStr) -> Option<FileId> {
+        self.file_slot(dir, name).ok().map(|at| self.dir(dir).files[at])
     }
-}
\ No newline at end of file
+
+    /// Finds `path`, creating any directory node missing along the way.
+    /// `None` if it's outside every root
+    fn ensure_dir(&mut self, path: &Path) -> Option<DirId> {
+        let (mut id, rest) = self.split_root(path)?;
+
+        for component in rest.components() {
+            let Component::Normal(name) = component else {
+                return None;
+            };
+
+            id = match self.dir_slot(id, name) {
+                Ok(at) => self.dir(id).dirs[at],
+                Err(at) => {
+                    let child = self.alloc_dir(Dir::new(name.to_os_string(), Some(id)));
+                    self.dir_mut(id).dirs.insert(at, child);
+                    child
+                }
+            };
+        }
+
+        Some(id)
+    }
+
+    fn alloc_dir(&mut self, dir: Dir) -> DirId {
+        match self.free_dirs.pop() {
+            Some(id) => {
+                self.dirs[id as usize] = Some(dir);
+                id
+            }
+            None => {
+                self.dirs.push(Some(dir));
+                (self.dirs.len() - 1) as DirId
+            }
+        }
+    }
+
+    fn alloc_file(&mut self, file: File) -> FileId {
+        match self.free_files.pop() {
+            Some(id) => {
+                self.files[id as usize] = Some(file);
+                id
+            }
+            None => {
+                self.files.push(Some(file));
+                (self.files.len() - 1) as FileId
+            }
+        }
+    }
+
+    /// Unlinks a file from its directory and frees its slot
+    fn free_file(&mut self, id: FileId) {
+        self.unlink_file(id);
+
+        self.files[id as usize] = None;
+        self.free_files.push(id);
+        self.len -= 1;
+    }
+
+    /// Unlinks a directory from its parent and frees it along with everything inside
+    fn free_dir(&mut self, id: DirId) {
+        self.detach_dir(id);
+
+        let mut stack = vec![id];
+        while let Some(d) = stack.pop() {
+            let Some(dir) = self.dirs[d as usize].take() else {
+                continue;
+            };
+
+            for f in dir.files {
+                self.files[f as usize] = None;
+                self.free_files.push(f);
+                self.len -= 1;
+            }
+
+            stack.extend(dir.dirs);
+            self.free_dirs.push(d);
+        }
+    }
+
+    fn detach_dir(&mut self, id: DirId) {
+        if let Some(parent) = self.dir(id).parent {
+            if let Ok(at) = self.dir_slot(parent, &self.dir(id).name) {
+                self.dir_mut(parent).dirs.remove(at);
+            }
+        }
+    }
+
+    /// Takes a file out of its directory's list, the directory may already be gone
+    fn unlink_file(&mut self, id: FileId) {
+        let file = self.file(id);
+        if !matches!(self.dirs.get(file.parent as usize), Some(Some(_))) {
+            return;
+        }
+
+        if let Ok(at) = self.file_slot(file.parent, &file.name) {
+            let parent = file.parent;
+            self.dir_mut(parent).files.remove(at);
+        }
+    }
+
+    fn link_dir(&mut self, parent: DirId, id: DirId) {
+        let at = self.dir_slot(parent, &self.dir(id).name).unwrap_or_else(|at| at);
+        self.dir_mut(parent).dirs.insert(at, id);
+    }
+
+    fn link_file(&mut self, parent: DirId, id: FileId) {
+        let at = self.file_slot(parent, &self.file(id).name).unwrap_or_else(|at| at);
+        self.dir_mut(parent).files.insert(at, id);
+    }
+
+    /// Where the subdirectory `name` of `dir` is, or where it would go
+    fn dir_slot(&self, dir: DirId, name: &OsStr) -> std::result::Result<usize, usize> {
+        self.dir(dir).dirs.binary_search_by(|d| self.dir(*d).name.as_os_str().cmp(name))
+    }
+
+    /// Where the file `name` in `dir` is, or where it would go
+    fn file_slot(&self, dir: DirId, name: &OsStr) -> std::result::Result<usize, usize> {
+        self.dir(dir).files.binary_search_by(|f| self.file(*f).name.as_os_str().cmp(name))
+    }
+
+    fn count_files(&self, id: DirId) -> usize {
+        let mut stack = vec![id];
+        let mut count = 0;
+
+        while let Some(d) = stack.pop() {
+            let dir = self.dir(d);
+            count += dir.files.len();
+            stack.extend(&dir.dirs);
+        }
+
+        count
+    }
+}
+
+impl Dir {
+    fn new(name: OsString, parent: Option<DirId>) -> Self {
+        Dir { name, parent, dirs: Vec::new(), files: Vec::new() }
+    }
+}
+
+/// Walks the tree depth first, rebuilding each file's full path
+pub struct Entries<'a> {
+    tree: &'a Tree,
+    stack: Vec<(DirId, PathBuf)>,
+    current: Option<(PathBuf, std::slice::Iter<'a, FileId>)>,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = (PathBuf, Option<&'a FileMeta>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((dir_path, files)) = &mut self.current {
+                if let Some(id) = files.next() {
+                    let file = self.tree.file(*id);
+                    return Some((dir_path.join(&file.name), file.meta.as_ref()));
+                }
+            }
+
+            let (id, path) = self.stack.pop()?;
+            let dir = self.tree.dir(id);
+
+            for child in dir.dirs.iter().rev() {
+                self.stack.push((*child, path.join(&self.tree.dir(*child).name)));
+            }
+
+            self.current = Some((path, dir.files.iter()));
+        }
+    }
+}
+
+/// Walks the tree like `Entries`, but doesn't borrow it between batches, so a shared tree
+/// only stays locked while a batch is copied out. Folders are looked up again by path,
+/// ones removed in the meantime are skipped
+pub struct Batches {
+    stack: Vec<PathBuf>,
+
+    // Folder being walked and how many of its files were already taken
+    current: Option<(PathBuf, usize)>,
+}
+
+impl Batches {
+    /// Up to `max` more files, empty once the walk is done
+    pub fn next_batch(&mut self, tree: &Tree, max: usize) -> Vec<(PathBuf, Option<FileMeta>)> {
+        let mut batch = Vec::new();
+
+        while batch.len() < max {
+            let (path, taken) = match self.current.take() {
+                Some(current) => current,
+                None => match self.stack.pop() {
+                    Some(path) => (path, 0),
+                    None => break,
+                },
+            };
+            let Some(id) = tree.find_dir(&path) else {
+                continue;
+            };
+            let dir = tree.dir(id);
+
+            if taken == 0 {
+                for child in dir.dirs.iter().rev() {
+                    self.stack.push(path.join(&tree.dir(*child).name));
+                }
+            }
+
+            let files = &dir.files[taken.min(dir.files.len())..];
+            let take = files.len().min(max - batch.len());
+            batch.extend(files[..take].iter().map(|id| {
+                let file = tree.file(*id);
+                (path.join(&file.name), file.meta.clone())
+            }));
+
+            if take < files.len() {
+                self.current = Some((path, taken + take));
+            }
+        }
+
+        batch
+    }
+}
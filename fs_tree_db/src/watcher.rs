@@ -0,0 +1,499 @@
+use std::collections::{ BTreeSet, HashMap };
+use std::ops::Bound;
+use std::path::{ Path, PathBuf };
+use std::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
+use std::sync::mpsc::{ self, Receiver, RecvTimeoutError };
+use std::sync::{ Arc, RwLock };
+use std::thread::JoinHandle;
+use std::time::{ Duration, Instant, SystemTime };
+
+use notify::event::{ ModifyKind, RenameMode };
+use notify::{ Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher };
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::error::Result;
+use crate::excludes::{ ExcludeRules, Excluder };
+use crate::Tree;
+
+/// A tree shared between the app and the watcher that keeps it up to date
+pub type SharedTree = Arc<RwLock<Tree>>;
+
+/// How often polled directories are rescanned when the watch limit was hit
+pub const RESCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long the watcher thread waits for an event before checking timers
+const TICK: Duration = Duration::from_millis(250);
+
+/// How many changes subscribers can fall behind before they start lagging
+const CHANNEL_CAPACITY: usize = 4096;
+
+/// A single change applied to the tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Change {
+    Created(PathBuf),
+    /// A file, or a directory and everything under it, was removed
+    Removed(PathBuf),
+    Renamed {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Modified(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// How often directories that couldn't be watched get rescanned
+    pub rescan_interval: Duration,
+
+    /// Max number of directories to watch, the rest are polled.
+    /// `None` watches until the OS limit (`fs.inotify.max_user_watches` on Linux) is reached
+    pub max_watches: Option<usize>,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            rescan_interval: RESCAN_INTERVAL,
+            max_watches: None,
+        }
+    }
+}
+
+/// Keeps a `Tree` in sync with the disk.
+/// Stops watching when dropped.
+pub struct TreeWatcher {
+    changes: broadcast::Sender<Change>,
+    polled: Arc<AtomicUsize>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl TreeWatcher {
+    /// Watches every root of `tree`. `exclude` should be the rules the tree was built with
+    pub fn new(tree: SharedTree, exclude: impl Into<ExcludeRules>) -> Result<Self> {
+        TreeWatcher::with_options(tree, exclude, WatchOptions::default())
+    }
+
+    pub fn with_options(
+        tree: SharedTree,
+        exclude: impl Into<ExcludeRules>,
+        options: WatchOptions
+    ) -> Result<Self> {
+        let roots = tree.read().unwrap().roots();
+        let excluder = exclude.into().compile(&roots)?;
+        let (event_tx, event_rx) = mpsc::channel();
+        let watcher = notify::recommended_watcher(event_tx)?;
+
+        let (changes, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let polled = Arc::new(AtomicUsize::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut state = WatchState {
+            watcher,
+            tree,
+            excluder,
+            options,
+            changes: changes.clone(),
+            watched: BTreeSet::new(),
+            polled: BTreeSet::new(),
+            polled_count: polled.clone(),
+            limit_hit: false,
+            pending_rename: None,
+        };
+
+        // Register watches before returning so no change after `new` is missed
+        for root in &roots {
+            state.register_tree(root);
+        }
+
+        let thread_stop = stop.clone();
+        let handle = std::thread::spawn(move || state.run(event_rx, thread_stop));
+
+        Ok(TreeWatcher {
+            changes,
+            polled,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// Receives every change applied to the tree from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.changes.subscribe()
+    }
+
+    /// Number of directories being rescanned periodically instead of watched
+    pub fn polled_dirs(&self) -> usize {
+        self.polled.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for TreeWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Everything the watcher thread owns
+struct WatchState {
+    watcher: RecommendedWatcher,
+    tree: SharedTree,
+    excluder: Excluder,
+    options: WatchOptions,
+    changes: broadcast::Sender<Change>,
+
+    // Directories with an OS watch on them. Sorted, so the ones under a path are found with `under`
+    watched: BTreeSet<PathBuf>,
+    // Directories that couldn't be watched and get rescanned instead
+    polled: BTreeSet<PathBuf>,
+    polled_count: Arc<AtomicUsize>,
+    limit_hit: bool,
+
+    // A "moved from" waiting for its "moved to"
+    pending_rename: Option<(Option<usize>, PathBuf)>,
+}
+
+/// `path` and the directories under it in `dirs`. Paths sort by component,
+/// so they all come right after `path`
+fn under<'a>(dirs: &'a BTreeSet<PathBuf>, path: &'a Path) -> impl Iterator<Item = &'a PathBuf> {
+    dirs.range::<Path, _>((Bound::Included(path), Bound::Unbounded)).take_while(move |d| d.starts_with(path))
+}
+
+impl WatchState {
+    fn run(mut self, events: Receiver<notify::Result<Event>>, stop: Arc<AtomicBool>) {
+        let mut last_rescan = Instant::now();
+
+        while !stop.load(Ordering::Relaxed) {
+            match events.recv_timeout(TICK) {
+                Ok(Ok(event)) => self.handle_event(event),
+                Ok(Err(e)) => {
+                    if let notify::ErrorKind::MaxFilesWatch = e.kind {
+                        self.limit_hit = true;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    // A "moved from" with no "moved to" means it left the watched area
+                    self.flush_pending_rename();
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if !self.polled.is_empty() && last_rescan.elapsed() >= self.options.rescan_interval {
+                let dirs: Vec<PathBuf> = self.polled.iter().cloned().collect();
+                self.rescan(&dirs);
+                last_rescan = Instant::now();
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        if event.need_rescan() {
+            // The OS dropped events, nothing to do but look at everything again
+            let dirs: Vec<PathBuf> = self.watched
+                .iter()
+                .chain(self.polled.iter())
+                .cloned()
+                .collect();
+            self.rescan(&dirs);
+            return;
+        }
+
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                self.flush_pending_rename();
+                if let Some(path) = event.paths.into_iter().next() {
+                    self.pending_rename = Some((event.attrs.tracker(), path));
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                let Some(to) = event.paths.into_iter().next() else {
+                    return;
+                };
+
+                match self.pending_rename.take() {
+                    Some((tracker, from)) if tracker == event.attrs.tracker() => {
+                        self.renamed(from, to);
+                    }
+                    pending => {
+                        // Moved in from somewhere we don't watch
+                        self.pending_rename = pending;
+                        self.flush_pending_rename();
+                        self.created(&to);
+                    }
+                }
+            }
+            // Already handled through `From` and `To`
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {}
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                // Backends that can't tell which side of the rename this is
+                for path in event.paths {
+                    if path.exists() {
+                        self.created(&path);
+                    } else {
+                        self.removed(&path);
+                    }
+                }
+            }
+            EventKind::Create(_) => {
+                self.flush_pending_rename();
+                for path in event.paths {
+                    self.created(&path);
+                }
+            }
+            EventKind::Remove(_) => {
+                self.flush_pending_rename();
+                for path in event.paths {
+                    self.removed(&path);
+                }
+            }
+            EventKind::Modify(_) => {
+                for path in event.paths {
+                    // A write under `target/` would otherwise add the file
+                    if path.is_file() && !self.is_excluded(&path, false) {
+                        self.apply(Change::Modified(path));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn flush_pending_rename(&mut self) {
+        if let Some((_, from)) = self.pending_rename.take() {
+            self.removed(&from);
+        }
+    }
+
+    fn created(&mut self, path: &Path) {
+        if path.is_dir() {
+            if !self.is_excluded(path, true) {
+                self.register_tree(path);
+            }
+        } else if path.is_file() && !self.is_excluded(path, false) {
+            self.apply(Change::Created(path.to_path_buf()));
+        }
+    }
+
+    fn removed(&mut self, path: &Path) {
+        self.forget_dirs(path);
+        self.apply(Change::Removed(path.to_path_buf()));
+    }
+
+    fn renamed(&mut self, from: PathBuf, to: PathBuf) {
+        if !to.is_dir() {
+            if self.is_excluded(&to, false) {
+                self.apply(Change::Removed(from));
+            } else {
+                self.apply(Change::Renamed { from, to });
+            }
+            return;
+        }
+
+        // Watches are tied to the old path, so re-register everything under the new one
+        self.forget_dirs(&from);
+
+        if self.is_excluded(&to, true) {
+            self.apply(Change::Removed(from));
+            return;
+        }
+
+        // Watch first, anything that happens under `to` after the rename shows up must be caught
+        let found = self.watch_dirs(&to);
+        self.apply(Change::Renamed { from, to });
+        self.add_missing(found);
+    }
+
+    /// Watches `root` and every directory under it, adding any file the tree doesn't have yet
+    fn register_tree(&mut self, root: &Path) {
+        let found = self.watch_dirs(root);
+        self.add_missing(found);
+    }
+
+    /// Watches `root` and every directory under it, returns the files found along the way
+    fn watch_dirs(&mut self, root: &Path) -> Vec<PathBuf> {
+        // Ignore files are read once per directory on the way down, like a crawl does
+        let mut stack = vec![(root.to_path_buf(), self.excluder.ignores_for(root))];
+        let mut found = Vec::new();
+
+        while let Some((dir, ignores)) = stack.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+
+            self.register_dir(dir);
+
+            for entry in entries.flatten() {
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+
+                let path = entry.path();
+                if self.excluder.is_excluded(&path, file_type.is_dir(), &ignores) {
+                    continue;
+                }
+
+                if file_type.is_file() {
+                    found.push(path);
+                } else if file_type.is_dir() {
+                    let sub_ignores = self.excluder.enter(&path, &ignores, |name| path.join(name).is_file());
+                    stack.push((path, sub_ignores));
+                }
+            }
+        }
+
+        found
+    }
+
+    fn add_missing(&mut self, found: Vec<PathBuf>) {
+        let new_files: Vec<PathBuf> = {
+            let tree = self.tree.read().unwrap();
+            found
+                .into_iter()
+                .filter(|p| !tree.contains(p))
+                .collect()
+        };
+
+        for path in new_files {
+            self.apply(Change::Created(path));
+        }
+    }
+
+    /// Adds a watch on `dir`, or polls it once the watch limit was reached
+    fn register_dir(&mut self, dir: PathBuf) {
+        if self.watched.contains(&dir) || self.polled.contains(&dir) {
+            return;
+        }
+
+        let over_cap = self.options.max_watches.is_some_and(|max| self.watched.len() >= max);
+
+        if !self.limit_hit && !over_cap {
+            match self.watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    self.watched.insert(dir);
+                    return;
+                }
+                Err(e) => {
+                    if let notify::ErrorKind::MaxFilesWatch = e.kind {
+                        self.limit_hit = true;
+                    }
+                }
+            }
+        }
+
+        self.polled.insert(dir);
+        self.polled_count.store(self.polled.len(), Ordering::Relaxed);
+    }
+
+    /// Drops watches and polling for `path` and every directory under it
+    fn forget_dirs(&mut self, path: &Path) {
+        let gone: Vec<PathBuf> = under(&self.watched, path).cloned().collect();
+        for dir in &gone {
+            let _ = self.watcher.unwatch(dir);
+            self.watched.remove(dir);
+        }
+
+        let polled: Vec<PathBuf> = under(&self.polled, path).cloned().collect();
+        for dir in &polled {
+            self.polled.remove(dir);
+        }
+        self.polled_count.store(self.polled.len(), Ordering::Relaxed);
+
+        // Freed watches may be reusable now
+        if !gone.is_empty() {
+            self.limit_hit = false;
+        }
+    }
+
+    /// Compares each directory against what the tree has for it
+    fn rescan(&mut self, dirs: &[PathBuf]) {
+        self.flush_pending_rename();
+
+        for dir in dirs {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                if !dir.exists() {
+                    self.removed(dir);
+                }
+                continue;
+            };
+
+            let mut on_disk = HashMap::new();
+            let mut subdirs = Vec::new();
+            let ignores = self.excluder.ignores_for(dir);
+
+            for entry in entries.flatten() {
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+
+                if self.excluder.is_excluded(&entry.path(), file_type.is_dir(), &ignores) {
+                    continue;
+                }
+
+                if file_type.is_file() {
+                    let modified = entry.metadata().ok().and_then(|m| m.modified().ok());
+                    on_disk.insert(entry.path(), modified);
+                } else if file_type.is_dir() {
+                    subdirs.push(entry.path());
+                }
+            }
+
+            let known: HashMap<PathBuf, Option<SystemTime>> = self.tree
+                .read()
+                .unwrap()
+                .files_in(dir)
+                .into_iter()
+                .map(|(path, meta)| (path, meta.and_then(|m| m.modified)))
+                .collect();
+
+            for (path, modified) in &on_disk {
+                match known.get(path) {
+                    None => self.apply(Change::Created(path.clone())),
+                    Some(indexed_modified) if indexed_modified != modified => {
+                        self.apply(Change::Modified(path.clone()));
+                    }
+                    Some(_) => {}
+                }
+            }
+            for path in known.keys() {
+                if !on_disk.contains_key(path) {
+                    self.apply(Change::Removed(path.clone()));
+                }
+            }
+
+            for sub in subdirs {
+                let is_known = self.watched.contains(&sub) || self.polled.contains(&sub);
+                if !is_known {
+                    self.register_tree(&sub);
+                }
+            }
+
+            let vanished: Vec<PathBuf> = under(&self.watched, dir)
+                .chain(under(&self.polled, dir))
+                .filter(|d| d.parent() == Some(dir.as_path()) && !d.exists())
+                .cloned()
+                .collect();
+
+            for sub in vanished {
+                self.removed(&sub);
+            }
+        }
+    }
+
+    /// For single paths coming from events, reads the ignore files above `path` if enabled
+    fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        self.excluder.is_excluded_fresh(path, is_dir)
+    }
+
+    fn apply(&mut self, change: Change) {
+        let applied = self.tree.write().unwrap().apply(&change);
+
+        if applied {
+            // No subscribers is fine
+            let _ = self.changes.send(change);
+        }
+    }
+}
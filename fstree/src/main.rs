@@ -0,0 +1,596 @@
+use std::collections::BTreeMap;
+use std::io::{ IsTerminal, Write };
+use std::path::{ Path, PathBuf };
+use std::process::ExitCode;
+use std::time::Instant;
+
+use clap::{ Args, Parser as _, Subcommand };
+use fs_tree_db::registry::{ self, IndexConfig };
+use fs_tree_db::{ locations, CrawlOptions, ExcludeProfile, ExcludeRules, Tree, TreeBuilder };
+
+use query::{ format_size, CaseMode, DateOrder, Encoding, Normalization, ParseError, ParseOptions, Query, SizeUnits };
+
+use output::{ rfc3339, Output, OutputArgs };
+
+mod output;
+
+/// Build and search file indexes from the shell, shared with the file-explorer app
+#[derive(clap::Parser, Debug)]
+#[command(name = "fstree", version)]
+struct Cli {
+    /// Index list to use instead of the app's
+    #[arg(long, global = true, value_name = "FILE")]
+    registry: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Index ROOTS under NAME, replacing whatever NAME indexed before.
+    /// Without ROOTS, rebuilds NAME from the roots it already has
+    Index {
+        roots: Vec<PathBuf>,
+
+        #[arg(short, long, default_value = "default")]
+        name: String,
+
+        /// Built-in exclude profile to start from: everything (the default), developer or documents
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Extra .gitignore-style pattern to leave out, can be repeated
+        #[arg(short, long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+
+        #[command(flatten)]
+        crawl: CrawlArgs,
+    },
+
+    /// Rebuild indexes with their saved roots and excludes, all of them by default
+    Update {
+        names: Vec<String>,
+
+        #[command(flatten)]
+        crawl: CrawlArgs,
+    },
+
+    /// Print the files matching QUERY, exits with 1 if there are none.
+    /// With fuzzy: in it the best matches come first
+    Query {
+        /// Same syntax as the app's search box, several words are joined
+        #[arg(required = true)]
+        query: Vec<String>,
+
+        /// Index to search, can be repeated. All of them by default
+        #[arg(short, long = "index", value_name = "NAME")]
+        indexes: Vec<String>,
+
+        /// Stop after this many results
+        #[arg(short, long)]
+        limit: Option<usize>,
+
+        #[command(flatten)]
+        parse: ParseArgs,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    /// List the filters queries can use
+    Predicates,
+
+    /// List the built-in groups type: knows and their extensions
+    Types,
+
+    /// Show the roots, size and age of each index
+    Stats {
+        names: Vec<String>,
+
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print every file in the indexes, all of them by default
+    Export {
+        names: Vec<String>,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+/// Mirrors `CrawlOptions`
+#[derive(Args, Debug)]
+struct CrawlArgs {
+    /// Crawl threads, twice the core count by default
+    #[arg(long)]
+    workers: Option<usize>,
+
+    /// Most folders read at once, 1 to 4 is kinder to spinning disks
+    #[arg(long)]
+    max_io: Option<usize>,
+
+    /// Adjust how many folders are read at once while crawling
+    #[arg(long)]
+    auto_tune: bool,
+
+    /// Don't print progress
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+impl From<&CrawlArgs> for CrawlOptions {
+    fn from(args: &CrawlArgs) -> Self {
+        CrawlOptions { workers: args.workers, max_io: args.max_io, auto_tune: args.auto_tune }
+    }
+}
+
+/// Mirrors `ParseOptions`
+#[derive(Args, Debug, Default)]
+struct ParseArgs {
+    /// How dates like 01/02/2024 are read, yyyy-mm-dd works either way
+    #[arg(long, value_enum, default_value_t = DateOrderArg::DayFirst)]
+    date_order: DateOrderArg,
+
+    /// Whether 1kb is 1000 or 1024 bytes, in the query and in {size_human}
+    #[arg(long, value_enum, default_value_t = SizeUnitsArg::Decimal)]
+    size_units: SizeUnitsArg,
+
+    /// Case handling of has:, name: and parent:. Smart ignores case unless the term has capitals
+    #[arg(long, value_enum, default_value_t = CaseArg::Smart)]
+    case: CaseArg,
+
+    /// Unicode normalization of the query and the paths
+    #[arg(long, value_enum, default_value_t = NormalizationArg::Nfc)]
+    normalization: NormalizationArg,
+
+    /// Ignore accents, resume matches résumé
+    #[arg(long)]
+    fold_diacritics: bool,
+
+    /// Group of extensions for type:, like backup:bak,old. Can be repeated, a built-in
+    /// group of the same name is replaced
+    #[arg(long, value_name = "NAME:EXTS", value_parser = parse_type_add)]
+    type_add: Vec<(String, Vec<String>)>,
+
+    /// How contains: decodes files. Auto is UTF-8, or UTF-16 with a byte order mark
+    #[arg(long, value_enum, default_value_t = EncodingArg::Auto)]
+    encoding: EncodingArg,
+
+    /// Most of each file contains: reads, like 512kb or 1mib. 10 MiB by default
+    #[arg(long, value_name = "SIZE", value_parser = parse_read_limit)]
+    read_limit: Option<u64>,
+}
+
+/// kb is always 1000 bytes here, `--size-units` is for the query
+fn parse_read_limit(s: &str) -> Result<u64, String> {
+    query::sizes::parse_size(s, SizeUnits::Decimal)
+}
+
+fn parse_type_add(s: &str) -> Result<(String, Vec<String>), String> {
+    let (name, extensions) = s.split_once(':').ok_or("expected NAME:EXTS, like backup:bak,old")?;
+    let extensions: Vec<String> = extensions.split(',').map(query::filetypes::normalize).filter(|e| !e.is_empty()).collect();
+    if name.is_empty() || extensions.is_empty() {
+        return Err("expected NAME:EXTS, like backup:bak,old".to_string());
+    }
+
+    Ok((name.to_string(), extensions))
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+enum DateOrderArg {
+    #[default]
+    DayFirst,
+    MonthFirst,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+enum SizeUnitsArg {
+    #[default]
+    Decimal,
+    Binary,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+enum CaseArg {
+    Sensitive,
+    Insensitive,
+    #[default]
+    Smart,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+enum EncodingArg {
+    #[default]
+    Auto,
+    Utf8,
+    Utf16le,
+    Utf16be,
+    Latin1,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+enum NormalizationArg {
+    None,
+    #[default]
+    Nfc,
+    Nfkc,
+}
+
+impl From<&ParseArgs> for ParseOptions {
+    fn from(args: &ParseArgs) -> Self {
+        let date_order = match args.date_order {
+            DateOrderArg::DayFirst => DateOrder::DayFirst,
+            DateOrderArg::MonthFirst => DateOrder::MonthFirst,
+        };
+
+        let size_units = match args.size_units {
+            SizeUnitsArg::Decimal => SizeUnits::Decimal,
+            SizeUnitsArg::Binary => SizeUnits::Binary,
+        };
+
+        let case = match args.case {
+            CaseArg::Sensitive => CaseMode::Sensitive,
+            CaseArg::Insensitive => CaseMode::Insensitive,
+            CaseArg::Smart => CaseMode::Smart,
+        };
+        let normalization = match args.normalization {
+            NormalizationArg::None => Normalization::None,
+            NormalizationArg::Nfc => Normalization::Nfc,
+            NormalizationArg::Nfkc => Normalization::Nfkc,
+        };
+
+        let encoding = match args.encoding {
+            EncodingArg::Auto => Encoding::Auto,
+            EncodingArg::Utf8 => Encoding::Utf8,
+            EncodingArg::Utf16le => Encoding::Utf16le,
+            EncodingArg::Utf16be => Encoding::Utf16be,
+            EncodingArg::Latin1 => Encoding::Latin1,
+        };
+
+        // The same name twice adds to the group
+        let mut file_types = BTreeMap::<String, Vec<String>>::new();
+        for (name, extensions) in &args.type_add {
+            file_types.entry(name.clone()).or_default().extend(extensions.iter().cloned());
+        }
+
+        ParseOptions {
+            date_order,
+            size_units,
+            case,
+            normalization,
+            fold_diacritics: args.fold_diacritics,
+            file_types,
+            encoding,
+            read_limit: args.read_limit.unwrap_or(query::content::DEFAULT_READ_LIMIT),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let registry = cli.registry.unwrap_or_else(locations::index_registry);
+
+    let result = match cli.command {
+        Command::Index { roots, name, profile, exclude, crawl } =>
+            index(&registry, name, roots, profile, exclude, &crawl).await,
+        Command::Update { names, crawl } => update(&registry, &names, &crawl).await,
+        Command::Query { query: words, indexes, limit, parse, output } =>
+            query(&registry, &words.join(" "), &indexes, limit, &parse, &output),
+        Command::Predicates => predicates(),
+        Command::Types => types(),
+        Command::Stats { names, json } => stats(&registry, &names, json),
+        Command::Export { names, output } => query(&registry, "", &names, None, &ParseArgs::default(), &output),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("fstree: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+async fn index(
+    registry: &Path,
+    name: String,
+    roots: Vec<PathBuf>,
+    profile: Option<String>,
+    exclude: Vec<String>,
+    crawl: &CrawlArgs
+) -> Result<ExitCode, String> {
+    let mut configs = registry::read(registry);
+    let existing = configs.iter().position(|c| c.name == name);
+
+    let roots = match (roots.is_empty(), existing) {
+        (false, _) => absolute_roots(roots)?,
+        (true, Some(i)) => configs[i].roots.clone(),
+        (true, None) => return Err(format!("there's no index called {}, give it some folders to index", name)),
+    };
+
+    let mut profile = match (profile, existing) {
+        (Some(name), _) => builtin_profile(&name)?,
+        (None, Some(i)) => configs[i].profile.clone(),
+        (None, None) => ExcludeProfile::default(),
+    };
+
+    if !exclude.is_empty() {
+        profile = ExcludeProfile::new("custom", ExcludeRules {
+            patterns: profile.rules.patterns.into_iter().chain(exclude).collect(),
+            ..profile.rules
+        });
+    }
+
+    // Indexes saved before file names got their hash keep their file
+    let save_path = match existing {
+        Some(i) => configs[i].save_path.clone(),
+        None => registry::save_path_for(registry, &name),
+    };
+    let config = IndexConfig::new(name, roots, profile, &save_path);
+
+    build(&config, crawl).await?;
+
+    match existing {
+        Some(i) => configs[i] = config,
+        None => configs.push(config),
+    }
+    registry::write(registry, &configs).map_err(|e| format!("couldn't save the index list: {}", e))?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn update(registry: &Path, names: &[String], crawl: &CrawlArgs) -> Result<ExitCode, String> {
+    let mut configs = registry::read(registry);
+    check_names(&configs, names)?;
+
+    // One broken index shouldn't lose the ones already refreshed or stop the rest
+    let mut failed = 0;
+    for config in configs.iter_mut().filter(|c| names.is_empty() || names.contains(&c.name)) {
+        // The profile in the saved header wins, the app may have changed it
+        if let Ok(header) = Tree::read_header(&config.save_path) {
+            config.profile = header.exclude;
+        }
+
+        if let Err(e) = build(config, crawl).await {
+            eprintln!("fstree: {}", e);
+            failed += 1;
+        }
+    }
+
+    registry::write(registry, &configs).map_err(|e| format!("couldn't save the index list: {}", e))?;
+    Ok(if failed == 0 { ExitCode::SUCCESS } else { ExitCode::from(2) })
+}
+
+/// Crawls and saves one index, with progress on stderr if it's a terminal
+async fn build(config: &IndexConfig, crawl: &CrawlArgs) -> Result<(), String> {
+    let builder = TreeBuilder::with_roots(config.roots.clone())
+        .profile(config.profile.clone())
+        .options(crawl.into());
+
+    let show_progress = !crawl.quiet && std::io::stderr().is_terminal();
+    let mut progress = builder.progress();
+    let name = config.name.clone();
+
+    let reporter = tokio::spawn(async move {
+        while show_progress && progress.changed().await.is_ok() {
+            let p = progress.borrow_and_update().clone();
+            eprint!("\r\x1b[K{}: {} folders, {} files", name, p.dirs_visited, p.files_found);
+        }
+    });
+
+    let started = Instant::now();
+    let built = builder.build().await;
+    let _ = reporter.await;
+
+    let tree = built.map_err(|e| format!("couldn't index {}: {}", config.name, e))?;
+    tree.save(&config.save_path).map_err(|e| format!("couldn't save {}: {}", config.name, e))?;
+
+    if !crawl.quiet {
+        if show_progress {
+            eprint!("\r\x1b[K");
+        }
+        eprintln!("{}: {} files in {:.1?}", config.name, tree.len(), started.elapsed());
+
+        for error in tree.crawl_errors() {
+            eprintln!("  skipped {}: {}", error.path.display(), error.error);
+        }
+    }
+
+    Ok(())
+}
+
+/// Lines of a `contains:` match printed with `--lines` or `--json`
+const LINES_PER_RESULT: usize = 3;
+
+fn query(
+    registry: &Path,
+    query: &str,
+    names: &[String],
+    limit: Option<usize>,
+    parse: &ParseArgs,
+    output: &OutputArgs
+) -> Result<ExitCode, String> {
+    let configs = registry::read(registry);
+    check_names(&configs, names)?;
+
+    // Unlike the app, a query that's partly wrong is an error
+    let options: ParseOptions = parse.into();
+    let parsed = Query::parse_with(query, &options);
+    if !parsed.errors().is_empty() {
+        return Err(format!("invalid query\n{}", underline_errors(query, parsed.errors())));
+    }
+
+    let stdout = std::io::stdout();
+    let mut out = Output::new(std::io::BufWriter::new(stdout.lock()), output, options.size_units)?;
+    let mut remaining = limit.unwrap_or(usize::MAX);
+    let mut found = 0;
+
+    // Ranked queries print the best matches first, so they're all collected before
+    let mut ranked = Vec::new();
+
+    // Whoever reads the output stopped early, like `| head`
+    let mut closed = false;
+
+    for config in configs.iter().filter(|c| names.is_empty() || names.contains(&c.name)) {
+        if remaining == 0 || closed {
+            break;
+        }
+
+        let tree = match Tree::load(&config.save_path) {
+            Ok(tree) => tree,
+            Err(e) => {
+                eprintln!("fstree: skipping {} ({}), run `fstree index -n {}`", config.name, e, config.name);
+                continue;
+            }
+        };
+
+        parsed.for_each_match(tree.entries(), LINES_PER_RESULT, |path, meta, score, lines| {
+            if parsed.is_ranked() {
+                ranked.push((score, config.name.as_str(), path, meta.cloned(), lines));
+                return true;
+            }
+
+            if out.write(&config.name, &path, meta, &lines).is_err() {
+                closed = true;
+                return false;
+            }
+            remaining -= 1;
+            found += 1;
+            remaining > 0
+        });
+    }
+    if closed {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Best first, shorter paths win ties
+    ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.2.as_os_str().len().cmp(&b.2.as_os_str().len())));
+    for (_, index, path, meta, lines) in ranked.iter().take(remaining) {
+        if out.write(index, path, meta.as_ref(), lines).is_err() {
+            return Ok(ExitCode::SUCCESS);
+        }
+        found += 1;
+    }
+
+    let _ = out.flush();
+    Ok(if found > 0 { ExitCode::SUCCESS } else { ExitCode::from(1) })
+}
+
+/// The query with a `^^^` line under each error, like
+///
+/// ```text
+///   has:a sise:>1mb
+///         ^^^^ Unknown filter 'sise', did you mean `size`?
+/// ```
+fn underline_errors(query: &str, errors: &[ParseError]) -> String {
+    let mut text = String::new();
+
+    for error in errors {
+        // Columns are counted in chars so multi-byte names line up
+        let start = query[..error.span.start].chars().count();
+        let width = query[error.span.start..error.span.end].chars().count().max(1);
+
+        text += &format!("  {}\n  {}{} {}\n", query, " ".repeat(start), "^".repeat(width), error);
+    }
+
+    text.trim_end().to_string()
+}
+
+fn predicates() -> Result<ExitCode, String> {
+    for predicate in query::predicates() {
+        println!("{:<10} {}", predicate.name, predicate.description);
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn types() -> Result<ExitCode, String> {
+    for (name, extensions) in query::filetypes::BUILTIN {
+        println!("{:<13} {}", name, extensions.join(", "));
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn stats(registry: &Path, names: &[String], json: bool) -> Result<ExitCode, String> {
+    let configs = registry::read(registry);
+    check_names(&configs, names)?;
+
+    let mut report = Vec::new();
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+
+    for config in configs.iter().filter(|c| names.is_empty() || names.contains(&c.name)) {
+        let header = Tree::read_header(&config.save_path).ok();
+        let on_disk = std::fs::metadata(&config.save_path).map(|m| m.len()).ok();
+
+        if json {
+            report.push(serde_json::json!({
+                "name": config.name,
+                "roots": config.roots,
+                "profile": config.profile.name,
+                "save_path": config.save_path,
+                "files": header.as_ref().map(|h| h.files),
+                "created": header.as_ref().map(|h| rfc3339(h.created)),
+                "bytes": on_disk,
+            }));
+            continue;
+        }
+
+        let _ = writeln!(out, "{}", config.name);
+        for root in &config.roots {
+            let _ = writeln!(out, "  root      {}", root.display());
+        }
+        let _ = writeln!(out, "  excludes  {}", config.profile.name);
+
+        match (header, on_disk) {
+            (Some(header), Some(bytes)) => {
+                let _ = writeln!(out, "  files     {}", header.files);
+                let _ = writeln!(out, "  built     {}", rfc3339(header.created));
+                let _ = writeln!(out, "  saved     {} ({})", config.save_path.display(), format_size(bytes, SizeUnits::Binary));
+            }
+            _ => {
+                let _ = writeln!(out, "  not built yet, run `fstree index -n {}`", config.name);
+            }
+        }
+    }
+
+    if json {
+        let _ = writeln!(out, "{}", serde_json::Value::Array(report));
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn check_names(configs: &[IndexConfig], names: &[String]) -> Result<(), String> {
+    match names.iter().find(|name| !configs.iter().any(|c| &c.name == *name)) {
+        Some(name) => Err(format!("there's no index called {}", name)),
+        None => Ok(()),
+    }
+}
+
+/// Roots are saved as given, so relative ones have to be resolved first
+fn absolute_roots(roots: Vec<PathBuf>) -> Result<Vec<PathBuf>, String> {
+    roots
+        .into_iter()
+        .map(|root| {
+            let root = std::path::absolute(locations::expand_home(&root)).map_err(|e| e.to_string())?;
+            if root.is_dir() {
+                Ok(root)
+            } else {
+                Err(format!("{} isn't a folder", root.display()))
+            }
+        })
+        .collect()
+}
+
+fn builtin_profile(name: &str) -> Result<ExcludeProfile, String> {
+    ExcludeProfile::builtin()
+        .into_iter()
+        .find(|p| p.name.split_whitespace().next() == Some(name))
+        .ok_or_else(|| format!("unknown profile {}, expected everything, developer or documents", name))
+}
@@ -0,0 +1,242 @@
+use std::io::{ self, Write };
+use std::path::Path;
+use std::time::SystemTime;
+
+use chrono::{ DateTime, Local };
+use clap::Args;
+use fs_tree_db::{ FileKind, FileMeta };
+use query::{ format_size, LineMatch, SizeUnits };
+
+/// How matches are printed, one path per line unless one of these is given
+#[derive(Args, Debug)]
+pub struct OutputArgs {
+    /// One JSON object per line with the path, size, modified time and kind
+    #[arg(long, conflicts_with_all = ["null", "format"])]
+    json: bool,
+
+    /// End each result with a NUL instead of a newline, for `xargs -0`
+    #[arg(short = '0', long)]
+    null: bool,
+
+    /// Print each result with a template. Placeholders: {path} {name} {parent} {ext}
+    /// {size} {size_human} {modified} {kind} {index}, `{{` and `}}` for literal braces
+    #[arg(long, value_name = "TEMPLATE")]
+    format: Option<String>,
+
+    /// Print the lines contains: found under each path, JSON always has them
+    #[arg(long, conflicts_with_all = ["json", "null", "format"])]
+    lines: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Path,
+    Name,
+    Parent,
+    Ext,
+    Size,
+    SizeHuman,
+    Modified,
+    Kind,
+    Index,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Field(Field),
+}
+
+enum Style {
+    Plain,
+    Json,
+    Template(Vec<Piece>),
+}
+
+/// Writes results to stdout in the format picked on the command line
+pub struct Output<W: Write> {
+    out: W,
+    style: Style,
+    terminator: u8,
+
+    /// For `size_human`
+    units: SizeUnits,
+
+    /// With plain output, `contains:` lines under each path
+    lines: bool,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(out: W, args: &OutputArgs, units: SizeUnits) -> Result<Self, String> {
+        let style = match &args.format {
+            Some(template) => Style::Template(parse_template(template)?),
+            None if args.json => Style::Json,
+            None => Style::Plain,
+        };
+
+        Ok(Output { out, style, terminator: if args.null { b'\0' } else { b'\n' }, units, lines: args.lines })
+    }
+
+    pub fn write(&mut self, index: &str, path: &Path, meta: Option<&FileMeta>, lines: &[LineMatch]) -> io::Result<()> {
+        match &self.style {
+            Style::Plain => {
+                write!(self.out, "{}", path.display())?;
+
+                // Like `grep -n`, indented so paths stand out
+                if self.lines {
+                    for line in lines {
+                        write!(self.out, "\n  {}:{}", line.line, line.text)?;
+                    }
+                }
+            }
+            Style::Json => {
+                let json = serde_json::json!({
+                    "index": index,
+                    "path": path,
+                    "size": meta.map(|m| m.size),
+                    "size_human": meta.map(|m| format_size(m.size, self.units)),
+                    "modified": meta.and_then(|m| m.modified).map(rfc3339),
+                    "kind": meta.map(|m| kind_name(m.kind)),
+                    "lines": lines,
+                });
+                write!(self.out, "{}", json)?;
+            }
+            Style::Template(pieces) => {
+                for piece in pieces {
+                    match piece {
+                        Piece::Text(text) => self.out.write_all(text.as_bytes())?,
+                        Piece::Field(field) => write!(self.out, "{}", field_value(*field, index, path, meta, self.units))?,
+                    }
+                }
+            }
+        }
+
+        self.out.write_all(&[self.terminator])
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn parse_template(template: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err("unmatched { in --format, write {{ for a literal one".to_string()),
+                    }
+                }
+                let field = match name.as_str() {
+                    "path" => Field::Path,
+                    "name" => Field::Name,
+                    "parent" => Field::Parent,
+                    "ext" => Field::Ext,
+                    "size" => Field::Size,
+                    "size_human" => Field::SizeHuman,
+                    "modified" => Field::Modified,
+                    "kind" => Field::Kind,
+                    "index" => Field::Index,
+                    _ => return Err(format!("unknown placeholder {{{}}} in --format", name)),
+                };
+
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(Piece::Field(field));
+            }
+            '}' => return Err("unmatched } in --format, write }} for a literal one".to_string()),
+            // Lets templates spell out tabs and newlines without shell quoting tricks
+            '\\' if chars.peek() == Some(&'t') => {
+                chars.next();
+                text.push('\t');
+            }
+            '\\' if chars.peek() == Some(&'n') => {
+                chars.next();
+                text.push('\n');
+            }
+            _ => text.push(c),
+        }
+    }
+
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+
+    Ok(pieces)
+}
+
+fn field_value(field: Field, index: &str, path: &Path, meta: Option<&FileMeta>, units: SizeUnits) -> String {
+    let lossy = |p: Option<&std::ffi::OsStr>| p.map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+
+    match field {
+        Field::Path => path.display().to_string(),
+        Field::Name => lossy(path.file_name()),
+        Field::Parent => path.parent().map(|p| p.display().to_string()).unwrap_or_default(),
+        Field::Ext => lossy(path.extension()),
+        Field::Size => meta.map(|m| m.size.to_string()).unwrap_or_default(),
+        Field::SizeHuman => meta.map(|m| format_size(m.size, units)).unwrap_or_default(),
+        Field::Modified => meta.and_then(|m| m.modified).map(rfc3339).unwrap_or_default(),
+        Field::Kind => meta.map(|m| kind_name(m.kind).to_string()).unwrap_or_default(),
+        Field::Index => index.to_string(),
+    }
+}
+
+pub fn rfc3339(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
+}
+
+fn kind_name(kind: FileKind) -> &'static str {
+    match kind {
+        FileKind::File => "file",
+        FileKind::Dir => "dir",
+        FileKind::Symlink => "symlink",
+        FileKind::Other => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn templates_fill_in_fields() {
+        let args = OutputArgs { json: false, null: true, format: Some("{name}\\t{size} {size_human} {{{ext}}}".into()), lines: false };
+        let mut output = Output::new(Vec::new(), &args, SizeUnits::Decimal).unwrap();
+
+        let meta = FileMeta {
+            size: 1_500,
+            modified: None,
+            changed: None,
+            mode: 0o644,
+            kind: FileKind::File,
+            inode: None,
+        };
+        output.write("docs", &PathBuf::from("/home/a/notes.txt"), Some(&meta), &[]).unwrap();
+
+        assert_eq!(output.out, b"notes.txt\t1500 1.5 KB {txt}\0");
+
+        let bad = OutputArgs { json: false, null: false, format: Some("{nope}".into()), lines: false };
+        assert!(Output::new(Vec::new(), &bad, SizeUnits::Decimal).is_err());
+
+        let unterminated = OutputArgs { json: false, null: false, format: Some("{path".into()), lines: false };
+        assert!(Output::new(Vec::new(), &unterminated, SizeUnits::Decimal).is_err());
+    }
+}
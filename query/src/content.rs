@@ -0,0 +1,186 @@
+//! Reads files for `contains:`. Most files are read a line at a time, so a match
+//! near the top is found without reading the rest.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{ BufRead, BufReader, Read };
+use std::path::Path;
+
+use serde::{ Deserialize, Serialize };
+
+use crate::types::Pattern;
+
+/// How much of a file is read by default, later matches aren't found
+pub const DEFAULT_READ_LIMIT: u64 = 10 * 1024 * 1024;
+
+/// Lines longer than this are cut in snippets
+const SNIPPET_CHARS: usize = 200;
+
+/// How much of the start of a file is looked at for a byte order mark and NULs
+const SNIFF_BYTES: usize = 8 * 1024;
+
+/// How file contents are decoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// UTF-8, or UTF-16 when the file starts with a byte order mark
+    #[default]
+    Auto,
+    Utf8,
+    Utf16le,
+    Utf16be,
+
+    /// ISO-8859-1, every byte is a character
+    Latin1,
+}
+
+/// A line that matched, for showing next to a result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LineMatch {
+    /// Counting from 1
+    pub line: usize,
+
+    /// Trimmed, and cut if it's long
+    pub text: String,
+}
+
+/// Where `contains:` leaves the lines it found while matching, so a match
+/// doesn't have to be read again to show them
+#[derive(Debug)]
+pub struct LineSink {
+    max: usize,
+    lines: RefCell<Vec<LineMatch>>,
+}
+
+impl LineSink {
+    pub fn new(max: usize) -> Self {
+        LineSink { max, lines: RefCell::new(Vec::new()) }
+    }
+
+    /// Lines looked for per file, one is always needed to tell whether it matches
+    pub fn max(&self) -> usize {
+        self.max.max(1)
+    }
+
+    pub fn extend(&self, lines: Vec<LineMatch>) {
+        self.lines.borrow_mut().extend(lines);
+    }
+
+    /// In file order, the first `max` found by any `contains:` term
+    pub fn into_lines(self) -> Vec<LineMatch> {
+        let mut lines = self.lines.into_inner();
+        lines.sort_by_key(|l| l.line);
+        lines.dedup_by_key(|l| l.line);
+        lines.truncate(self.max);
+        lines
+    }
+}
+
+/// What `contains:` looks for, with the settings it was parsed with
+#[derive(Debug, Clone)]
+pub struct ContentSearch {
+    pub pattern: Pattern,
+    pub encoding: Encoding,
+
+    /// Bytes read per file at most
+    pub read_limit: u64,
+}
+
+impl ContentSearch {
+    /// The first `max` lines that match. Files that can't be read or look binary have none
+    pub fn search(&self, path: &Path, max: usize) -> Vec<LineMatch> {
+        let Ok(file) = File::open(path) else {
+            return Vec::new();
+        };
+        let mut reader = BufReader::with_capacity(64 * 1024, file.take(self.read_limit));
+
+        // Folders open fine on some systems and fail here
+        let Ok(head) = reader.fill_buf() else {
+            return Vec::new();
+        };
+        let head = &head[..head.len().min(SNIFF_BYTES)];
+        let (encoding, bom) = self.encoding.detect(head);
+
+        // Like ripgrep, a NUL means it isn't text. UTF-16 is full of them
+        let wide = matches!(encoding, Encoding::Utf16le | Encoding::Utf16be);
+        if !wide && head.contains(&0) {
+            return Vec::new();
+        }
+        reader.consume(bom);
+
+        let mut found = Vec::new();
+        let mut check = |number: usize, line: &str| {
+            if self.pattern.is_match(line) {
+                found.push(LineMatch { line: number, text: snippet(line) });
+            }
+            found.len() < max
+        };
+
+        if wide {
+            // Not split on `\n` bytes, they're half a character here
+            let mut bytes = Vec::new();
+            if reader.read_to_end(&mut bytes).is_err() {
+                return Vec::new();
+            }
+            let text = decode_utf16(&bytes, encoding == Encoding::Utf16le);
+            for (i, line) in text.lines().enumerate() {
+                if !check(i + 1, line) {
+                    break;
+                }
+            }
+        } else {
+            let mut bytes = Vec::new();
+            let mut number = 0;
+            while let Ok(1..) = reader.read_until(b'\n', &mut bytes) {
+                number += 1;
+                let line = match encoding {
+                    Encoding::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+                    _ => String::from_utf8_lossy(&bytes).into_owned(),
+                };
+                if !check(number, line.trim_end_matches(['\n', '\r'])) {
+                    break;
+                }
+                bytes.clear();
+            }
+        }
+
+        found
+    }
+}
+
+impl Encoding {
+    /// The encoding to read with, and how long the byte order mark is
+    fn detect(self, head: &[u8]) -> (Encoding, usize) {
+        let bom = match head {
+            [0xEF, 0xBB, 0xBF, ..] => Some((Encoding::Utf8, 3)),
+            [0xFF, 0xFE, ..] => Some((Encoding::Utf16le, 2)),
+            [0xFE, 0xFF, ..] => Some((Encoding::Utf16be, 2)),
+            _ => None,
+        };
+
+        match (self, bom) {
+            (Encoding::Auto, Some(found)) => found,
+            (Encoding::Auto, None) => (Encoding::Utf8, 0),
+            // A mark that agrees with the setting is skipped
+            (_, Some((found, len))) if found == self => (self, len),
+            _ => (self, 0),
+        }
+    }
+}
+
+fn decode_utf16(bytes: &[u8], little_endian: bool) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| match little_endian {
+        true => u16::from_le_bytes([pair[0], pair[1]]),
+        false => u16::from_be_bytes([pair[0], pair[1]]),
+    });
+
+    char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+}
+
+fn snippet(line: &str) -> String {
+    let line = line.trim();
+    match line.char_indices().nth(SNIPPET_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
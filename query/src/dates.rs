@@ -0,0 +1,147 @@
+use chrono::{ Datelike, Days, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta };
+use serde::{ Deserialize, Serialize };
+
+use crate::types::Unit;
+
+/// How `01/02/2024` is read, ISO dates like `2024-02-01` work either way
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateOrder {
+    /// 01/02/2024 is the 1st of February
+    #[default]
+    DayFirst,
+
+    /// 01/02/2024 is January 2nd
+    MonthFirst,
+}
+
+/// Parses a date value, relative ones count from `now`, in local time.
+///
+/// - A day, minute or second: `2024-01-31`, `31/01/2024`, `2024-01-31T14:30`, `14:30` for today
+/// - A month or a year: `2024-01`, `2024`
+/// - A period: `today`, `yesterday`, `thisweek`, `lastweek`, `thismonth`, `lastmonth`, `thisyear`, `lastyear`
+/// - An age: `30min`, `12h`, `7d`, `2w`, `6mo`, `1y`, and `s` for seconds
+pub fn parse_date(s: &str, order: DateOrder, now: NaiveDateTime) -> Result<Unit, String> {
+    let s = s.trim();
+
+    if let Some((first, last)) = period(&s.to_lowercase(), now) {
+        return Ok(Unit::Date { first, last });
+    }
+
+    if let Some(age) = parse_age(s) {
+        return Ok(Unit::Age(age));
+    }
+
+    let (first, next) = parse_datetime(s, order, now)?;
+    Ok(Unit::Date { first, last: next - TimeDelta::nanoseconds(1) })
+}
+
+/// `Some` for words like `today`, as the first and last moment they cover
+fn period(word: &str, now: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let today = now.date();
+    let week = today - Days::new(today.weekday().num_days_from_monday().into());
+    let month = today.with_day(1)?;
+    let year = month.with_month(1)?;
+
+    let (first, next) = match word {
+        "today" => (today, today + Days::new(1)),
+        "yesterday" => (today - Days::new(1), today),
+        "thisweek" => (week, week + Days::new(7)),
+        "lastweek" => (week - Days::new(7), week),
+        "thismonth" => (month, month + Months::new(1)),
+        "lastmonth" => (month - Months::new(1), month),
+        "thisyear" => (year, year + Months::new(12)),
+        "lastyear" => (year - Months::new(12), year),
+        _ => return None,
+    };
+
+    let midnight = |day: NaiveDate| day.and_time(NaiveTime::MIN);
+    Some((midnight(first), midnight(next) - TimeDelta::nanoseconds(1)))
+}
+
+/// `7d` and the like, months and years are 30 and 365 days
+fn parse_age(s: &str) -> Option<TimeDelta> {
+    let idx = s.find(|c: char| !c.is_ascii_digit()).filter(|&i| i > 0)?;
+    let (num_part, suffix) = s.split_at(idx);
+    let num: i64 = num_part.parse().ok()?;
+
+    match suffix.to_lowercase().as_str() {
+        "s" => TimeDelta::try_seconds(num),
+        "min" => TimeDelta::try_minutes(num),
+        "h" => TimeDelta::try_hours(num),
+        "d" => TimeDelta::try_days(num),
+        "w" => TimeDelta::try_weeks(num),
+        "mo" => TimeDelta::try_days(num.checked_mul(30)?),
+        "y" => TimeDelta::try_days(num.checked_mul(365)?),
+        _ => None,
+    }
+}
+
+/// When the date starts, and when whatever comes after it does: the next day, minute or month
+fn parse_datetime(s: &str, order: DateOrder, now: NaiveDateTime) -> Result<(NaiveDateTime, NaiveDateTime), String> {
+    let (date_part, time_part) = match s.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None if s.contains(':') => ("", Some(s)),
+        None => (s, None),
+    };
+
+    let date = match date_part {
+        "" => Some(now.date()),
+        _ => parse_day(date_part, order),
+    };
+
+    let Some(date) = date else {
+        if let Some(year) = parse_year(date_part).filter(|_| time_part.is_none()) {
+            return Ok(year);
+        }
+
+        // yyyy-mm
+        return NaiveDate::parse_from_str(&format!("{}-01", date_part), "%Y-%m-%d")
+            .ok()
+            .filter(|_| time_part.is_none())
+            .and_then(|month| Some((month, month.checked_add_months(Months::new(1))?)))
+            .map(|(month, next)| (month.and_time(NaiveTime::MIN), next.and_time(NaiveTime::MIN)))
+            .ok_or_else(|| match order {
+                DateOrder::DayFirst => format!("Could not parse date '{}', dates are yyyy-mm-dd or dd/mm/yyyy", s),
+                DateOrder::MonthFirst => format!("Could not parse date '{}', dates are yyyy-mm-dd or mm/dd/yyyy", s),
+            });
+    };
+
+    let Some(time_part) = time_part else {
+        let first = date.and_time(NaiveTime::MIN);
+        return Ok((first, first + TimeDelta::days(1)));
+    };
+
+    NaiveTime::parse_from_str(time_part, "%H:%M:%S")
+        .map(|time| (time, TimeDelta::seconds(1)))
+        .or_else(|_| NaiveTime::parse_from_str(time_part, "%H:%M").map(|time| (time, TimeDelta::minutes(1))))
+        .map(|(time, precision)| (date.and_time(time), date.and_time(time) + precision))
+        .map_err(|_| format!("Could not parse time '{}', times are hh:mm or hh:mm:ss", time_part))
+}
+
+/// `yyyy`, as when the year starts and when the next one does
+fn parse_year(s: &str) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    if s.len() != 4 || !s.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let first = NaiveDate::from_ymd_opt(s.parse().ok()?, 1, 1)?;
+    let next = first.checked_add_months(Months::new(12))?;
+    Some((first.and_time(NaiveTime::MIN), next.and_time(NaiveTime::MIN)))
+}
+
+fn parse_day(s: &str, order: DateOrder) -> Option<NaiveDate> {
+    let slashed = match order {
+        DateOrder::DayFirst => "%d/%m/%Y",
+        DateOrder::MonthFirst => "%m/%d/%Y",
+    };
+
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(s, slashed))
+        .ok()
+}
+
+/// What `modified` compares against, the current time in the same local time as the dates
+pub fn now() -> NaiveDateTime {
+    Local::now().naive_local()
+}
@@ -0,0 +1,44 @@
+use std::collections::BTreeMap;
+
+/// The groups `type:` knows without any settings, as extensions without the dot.
+/// Close to ripgrep's `--type-list` where the two overlap
+pub const BUILTIN: &[(&str, &[&str])] = &[
+    ("archive", &[
+        "7z", "apk", "bz2", "cab", "deb", "dmg", "gz", "iso", "jar", "lz", "lz4", "lzma", "rar", "rpm", "tar",
+        "tar.bz2", "tar.gz", "tar.xz", "tar.zst", "tbz2", "tgz", "txz", "xz", "z", "zip", "zst",
+    ]),
+    ("audio", &["aac", "aif", "aiff", "alac", "ape", "flac", "m4a", "mid", "midi", "mp3", "oga", "ogg", "opus", "wav", "wma"]),
+    ("code", &[
+        "asm", "bash", "c", "cc", "clj", "cpp", "cs", "css", "cxx", "dart", "ex", "exs", "fish", "fs", "go", "h",
+        "hh", "hpp", "hs", "html", "java", "js", "jsx", "kt", "kts", "lua", "m", "mjs", "ml", "php", "pl", "ps1",
+        "py", "r", "rb", "rs", "scala", "scss", "sh", "sql", "svelte", "swift", "ts", "tsx", "vue", "zig", "zsh",
+    ]),
+    ("document", &["doc", "docx", "epub", "md", "odt", "pages", "pdf", "rst", "rtf", "tex", "txt"]),
+    ("font", &["eot", "otf", "ttc", "ttf", "woff", "woff2"]),
+    ("image", &[
+        "avif", "bmp", "gif", "heic", "heif", "ico", "jpeg", "jpg", "jxl", "png", "psd", "raw", "svg", "tif",
+        "tiff", "webp",
+    ]),
+    ("presentation", &["key", "odp", "ppt", "pptx"]),
+    ("spreadsheet", &["csv", "numbers", "ods", "tsv", "xls", "xlsm", "xlsx"]),
+    ("video", &["3gp", "avi", "flv", "m4v", "mkv", "mov", "mp4", "mpeg", "mpg", "ogv", "webm", "wmv"]),
+];
+
+/// The extensions of the group `name`, groups in `user` replace built-in ones of the same name
+pub fn lookup<'a>(name: &str, user: &'a BTreeMap<String, Vec<String>>) -> Option<Vec<&'a str>> {
+    if let Some(extensions) = user.get(name) {
+        return Some(extensions.iter().map(String::as_str).collect());
+    }
+
+    BUILTIN.iter().find(|(group, _)| *group == name).map(|(_, extensions)| extensions.to_vec())
+}
+
+/// Every group name, built-in and from `user`, for suggestions
+pub fn names(user: &BTreeMap<String, Vec<String>>) -> impl Iterator<Item = &str> {
+    BUILTIN.iter().map(|(name, _)| *name).chain(user.keys().map(String::as_str))
+}
+
+/// How extensions are compared, `.JPG` and `jpg` are the same
+pub fn normalize(extension: &str) -> String {
+    extension.trim().trim_start_matches('.').to_lowercase()
+}
@@ -0,0 +1,228 @@
+use std::path::Path;
+
+use chrono::{ DateTime, Local };
+use fs_tree_db::FileKind;
+
+use crate::{ dates, fuzzy };
+use crate::content::{ LineMatch, LineSink };
+use crate::types::{ Arg, Candidate, Pattern, PatternKind, Score, Unit };
+
+/// Returns true if the full path contains the text or matches the pattern
+pub fn has(candidate: &Candidate, args: &Arg) -> Result<Score, String> {
+    matches_text(Some(candidate.path), args, false).map(pass)
+}
+
+/// Matches the file or folder name only, `name:*.rs`
+pub fn name(candidate: &Candidate, args: &Arg) -> Result<Score, String> {
+    matches_text(last_component(candidate.path), args, true).map(pass)
+}
+
+/// Matches the folder the file is in, `parent:src` or `parent:**/tests`
+pub fn parent(candidate: &Candidate, args: &Arg) -> Result<Score, String> {
+    let parent = Path::new(candidate.path).parent().and_then(|p| p.to_str());
+    matches_text(parent, args, false).map(pass)
+}
+
+/// Letters typed in order somewhere in the name, scored like fzf. `fuzzy:mdrs` finds
+/// `main_driver.rs`. With a `/` in it the whole path is matched instead
+pub fn fuzzy(candidate: &Candidate, args: &Arg) -> Result<Score, String> {
+    let (needle, folding) = match args {
+        Arg::Pattern(Pattern { kind: PatternKind::Text(needle), folding }) => (needle.as_str(), Some(folding)),
+        // Before the text is compiled, when checking arguments
+        Arg::Literal(needle) | Arg::Path(needle) => (needle.as_str(), None),
+        _ => return Err("Invalid argument. fuzzy takes plain text.".to_string()),
+    };
+
+    let text = if needle.contains('/') { Some(candidate.path) } else { last_component(candidate.path) };
+    let Some(text) = text else {
+        return Ok(None);
+    };
+
+    match folding {
+        Some(folding) => Ok(fuzzy::score(needle, text, &folding.apply(text))),
+        None => Ok(fuzzy::score(needle, text, text)),
+    }
+}
+
+/// Files ending in one of the extensions, whatever their case. `ext:rs` or `ext:jpg,png`,
+/// and `foo.rs.bak` is not a Rust file
+pub fn ext(candidate: &Candidate, args: &Arg) -> Result<Score, String> {
+    has_extension(candidate, args).map(pass)
+}
+
+/// Files in a group of extensions, `type:image`. Groups are built in or come from the settings
+pub fn file_type(candidate: &Candidate, args: &Arg) -> Result<Score, String> {
+    has_extension(candidate, args).map(pass)
+}
+
+fn has_extension(candidate: &Candidate, args: &Arg) -> Result<bool, String> {
+    let Arg::Extensions(extensions) = args else {
+        return Err("Invalid argument. Expected extensions like rs or jpg,png.".to_string());
+    };
+    let Some(name) = last_component(candidate.path) else {
+        return Ok(false);
+    };
+
+    // `tar.gz` takes two parts, and `.gitignore` has no extension
+    let name = name.to_lowercase();
+    let matched = extensions.iter().any(|extension| {
+        name.len() > extension.len() + 1
+            && name.ends_with(extension.as_str())
+            && name[..name.len() - extension.len()].ends_with('.')
+    });
+
+    // Only asks the disk about names that could match
+    Ok(matched && candidate.meta().is_some_and(|m| m.kind != FileKind::Dir))
+}
+
+/// Files with a line containing the text or matching the regex, `contains:TODO` or
+/// `contains:/fn \w+_test/`. Binary files never match
+pub fn contains(candidate: &Candidate, args: &Arg) -> Result<Score, String> {
+    let mut lines = Vec::new();
+    let matched = search_content(candidate, args, &mut lines)?;
+
+    if let Some(sink) = candidate.lines.filter(|_| matched) {
+        sink.extend(lines);
+    }
+    Ok(pass(matched))
+}
+
+/// Adds the lines found to `lines`, as many as the candidate wants shown
+fn search_content(candidate: &Candidate, args: &Arg, lines: &mut Vec<LineMatch>) -> Result<bool, String> {
+    match args {
+        Arg::Content(search) => {
+            let max = candidate.lines.map_or(1, LineSink::max);
+            let found = search.search(Path::new(candidate.path), max);
+            let matched = !found.is_empty();
+            lines.extend(found);
+            Ok(matched)
+        }
+        Arg::Pattern(Pattern { kind: PatternKind::Glob { .. }, .. }) => {
+            Err("contains doesn't take globs, quote the text to look for * or ?".to_string())
+        }
+        // Before the text is compiled, when checking arguments
+        Arg::Literal(_) | Arg::Path(_) | Arg::Pattern(_) => Ok(false),
+        Arg::Group(group_args) => {
+            // AND logic, everything is checked when parsing but files stop being read at the first miss
+            let mut all = true;
+            for cond in group_args {
+                if all || !cond.reads_content() {
+                    all &= search_content(candidate, cond, lines)?;
+                }
+            }
+            Ok(all)
+        }
+        _ => Err("Invalid argument. contains takes text or a /regex/.".to_string()),
+    }
+}
+
+/// Filters that only pass or fail all score the same
+fn pass(matched: bool) -> Score {
+    matched.then_some(0)
+}
+
+/// Checks `text` against plain text, a glob or a regex, or all of a group.
+/// `text` is a name when `is_name`, and a path otherwise.
+/// Plain text is a `Pattern` once parsed, so it gets the same case and Unicode handling
+fn matches_text(text: Option<&str>, args: &Arg, is_name: bool) -> Result<bool, String> {
+    match args {
+        Arg::Literal(arg) | Arg::Path(arg) => Ok(text.is_some_and(|t| t.contains(arg.as_str()))),
+        Arg::Pattern(Pattern { kind: PatternKind::Glob { names_only: false, .. }, .. }) if is_name => {
+            Err("Names can't contain /, use has: or parent: for globs with folders".to_string())
+        }
+        Arg::Pattern(pattern) if pattern.names_only() => {
+            Ok(text.and_then(last_component).is_some_and(|name| pattern.is_match(name)))
+        }
+        Arg::Pattern(pattern) => Ok(text.is_some_and(|t| pattern.is_match(t))),
+        Arg::Group(group_args) => {
+            // AND logic, every condition is looked at so all of them get checked
+            let mut all = true;
+            for cond in group_args {
+                all &= matches_text(text, cond, is_name)?;
+            }
+            Ok(all)
+        }
+        _ => Err("Invalid argument. Expected text, a glob or a /regex/.".to_string()),
+    }
+}
+
+fn last_component(path: &str) -> Option<&str> {
+    Path::new(path).file_name().and_then(|name| name.to_str())
+}
+
+pub fn size(candidate: &Candidate, args: &Arg) -> Result<Score, String> {
+    // Only files have a size worth comparing
+    let file_size = candidate.meta().filter(|m| m.kind == FileKind::File).map(|m| m.size);
+
+    compare(args, file_size, "a size", &|unit| match unit {
+        Unit::Size(size) => Some(Bounds { first: *size, last: *size, reversed: false }),
+        _ => None,
+    })
+    .map(pass)
+}
+
+/// Filter by last modification time, in local time like the dates it's compared to
+pub fn modified(candidate: &Candidate, args: &Arg) -> Result<Score, String> {
+    let modified_time = candidate
+        .meta()
+        .and_then(|m| m.modified)
+        .map(|time| DateTime::<Local>::from(time).naive_local());
+
+    let now = dates::now();
+    compare(args, modified_time, "a date or an age", &|unit| match unit {
+        Unit::Date { first, last } => Some(Bounds { first: *first, last: *last, reversed: false }),
+
+        // Older is earlier, so `<7d` is after a week ago
+        Unit::Age(age) => {
+            let when = now - *age;
+            Some(Bounds { first: when, last: when, reversed: true })
+        }
+        _ => None,
+    })
+    .map(pass)
+}
+
+/// The first and last value a `Unit` stands for
+struct Bounds<T> {
+    first: T,
+    last: T,
+
+    /// Bigger units are smaller values, like ages and times
+    reversed: bool,
+}
+
+/// Checks `value` against a conditional, a range or a group of them, all of which
+/// have to hold. `bounds` reads a `Unit` of the right kind.
+/// The arguments are checked even without a `value`, so mistakes show up when parsing
+fn compare<T: PartialOrd + Copy>(
+    args: &Arg,
+    value: Option<T>,
+    expected: &str,
+    bounds: &dyn Fn(&Unit) -> Option<Bounds<T>>
+) -> Result<bool, String> {
+    let bounds_of = |unit: &Unit| bounds(unit).ok_or_else(|| format!("Invalid value, expected {}.", expected));
+
+    match args {
+        Arg::Conditional { operator, value: unit } => {
+            let Bounds { first, last, reversed } = bounds_of(unit)?;
+            let operator = if reversed { operator.reversed() } else { *operator };
+            Ok(value.is_some_and(|v| operator.holds(v, first, last)))
+        }
+        Arg::Range { from, to } => {
+            // Ends of either kind, `7d..2d` and `2024-01..1d` both work
+            let (from, to) = (bounds_of(from)?, bounds_of(to)?);
+            let first = if to.first < from.first { to.first } else { from.first };
+            let last = if to.last > from.last { to.last } else { from.last };
+            Ok(value.is_some_and(|v| first <= v && v <= last))
+        }
+        Arg::Group(group_args) => {
+            // AND logic, every condition is looked at so all of them get checked
+            let mut all = true;
+            for cond in group_args {
+                all &= compare(cond, value, expected, bounds)?;
+            }
+            Ok(all)
+        }
+        _ => Err("Invalid argument. Expected conditional, range or group.".to_string()),
+    }
+}
@@ -0,0 +1,120 @@
+//! Scores how well typed letters match a name, in the spirit of fzf's first algorithm:
+//! the letters have to show up in order, and matches at the start of words, after
+//! separators, at camelCase humps and right after each other score higher.
+
+const SCORE_MATCH: i32 = 16;
+const SCORE_GAP_START: i32 = -3;
+const SCORE_GAP_EXTENSION: i32 = -1;
+
+/// A match right after `/`, `_`, a space and the like
+const BONUS_BOUNDARY: i32 = SCORE_MATCH / 2;
+
+/// `B` in `fooBar` or `1` in `foo1`
+const BONUS_CAMEL: i32 = BONUS_BOUNDARY + SCORE_GAP_EXTENSION;
+
+/// Worth more than any gap, so `abc` beats `a_b_c`
+const BONUS_CONSECUTIVE: i32 = -(SCORE_GAP_START + SCORE_GAP_EXTENSION);
+
+/// The first letter typed usually is the start of the name
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Lower,
+    Upper,
+    Number,
+    Letter,
+    Delimiter,
+}
+
+fn class(c: char) -> Class {
+    match c {
+        _ if c.is_lowercase() => Class::Lower,
+        _ if c.is_uppercase() => Class::Upper,
+        _ if c.is_numeric() => Class::Number,
+        _ if c.is_alphabetic() => Class::Letter,
+        _ => Class::Delimiter,
+    }
+}
+
+fn bonus(prev: Class, current: Class) -> i32 {
+    match (prev, current) {
+        (_, Class::Delimiter) => BONUS_BOUNDARY,
+        (Class::Delimiter, _) => BONUS_BOUNDARY,
+        (Class::Lower, Class::Upper) => BONUS_CAMEL,
+        (Class::Lower | Class::Upper | Class::Letter, Class::Number) => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+/// `None` if `needle`'s letters aren't all in `folded` in order, otherwise
+/// a score of at least 1, higher for better matches. `folded` is `original` after case
+/// and Unicode folding, word starts and camelCase humps are found in `original`
+pub fn score(needle: &str, original: &str, folded: &str) -> Option<u32> {
+    let needle: Vec<char> = needle.chars().collect();
+    let haystack: Vec<char> = folded.chars().collect();
+    if needle.is_empty() {
+        return Some(1);
+    }
+
+    // Folding that merged or split letters leaves nothing to line up with, the folded ones have to do
+    let mut classes: Vec<Class> = original.chars().map(class).collect();
+    if classes.len() != haystack.len() {
+        classes = haystack.iter().map(|&c| class(c)).collect();
+    }
+
+    // Where the first full match ends
+    let mut found = 0;
+    let end = haystack.iter().position(|&c| {
+        if c == needle[found] {
+            found += 1;
+        }
+        found == needle.len()
+    })? + 1;
+
+    // Then back to the latest start for that end, the shortest window
+    let mut left = needle.len();
+    let start = (0..end).rev().find(|&i| {
+        if haystack[i] == needle[left - 1] {
+            left -= 1;
+        }
+        left == 0
+    })?;
+
+    let mut score = 0;
+    let mut matched = 0;
+    let mut in_gap = false;
+    let mut consecutive = 0;
+    let mut first_bonus = 0;
+    let mut prev = if start == 0 { Class::Delimiter } else { classes[start - 1] };
+
+    for (&c, &current) in haystack[start..end].iter().zip(&classes[start..end]) {
+
+        if matched < needle.len() && c == needle[matched] {
+            let mut bonus = bonus(prev, current);
+
+            // A run keeps the bonus of where it started
+            if consecutive == 0 {
+                first_bonus = bonus;
+            } else {
+                if bonus >= BONUS_BOUNDARY && bonus > first_bonus {
+                    first_bonus = bonus;
+                }
+                bonus = bonus.max(first_bonus).max(BONUS_CONSECUTIVE);
+            }
+
+            score += SCORE_MATCH + if matched == 0 { bonus * BONUS_FIRST_CHAR_MULTIPLIER } else { bonus };
+            matched += 1;
+            consecutive += 1;
+            in_gap = false;
+        } else {
+            score += if in_gap { SCORE_GAP_EXTENSION } else { SCORE_GAP_START };
+            consecutive = 0;
+            in_gap = true;
+        }
+
+        prev = current;
+    }
+
+    Some(score.max(1) as u32)
+}
@@ -0,0 +1,166 @@
+use serde::Serialize;
+
+use crate::error::ParseError;
+
+/// Byte offsets into the query, `end` is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A `function:args` term, quotes and grouped args included as typed
+    Term(String),
+    LParen,
+    RParen,
+
+    /// `AND`, also implied between two terms
+    And,
+
+    /// `OR` or `|`
+    Or,
+
+    /// `NOT` or a leading `-`
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// Splits a query into terms, parentheses and operators.
+///
+/// Whitespace only separates terms outside of quotes, regexes and argument groups,
+/// so `has:"my report"`, `has:/a|b/` and `size:(>1kb, <1mb)` are single terms.
+/// Operators are case sensitive, `and` or `or` on their own are terms.
+pub fn tokenize(q: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = q.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        let single = match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' => Some(TokenKind::LParen),
+            ')' => Some(TokenKind::RParen),
+            '|' => Some(TokenKind::Or),
+            '-' => Some(TokenKind::Not),
+            _ => None,
+        };
+
+        if let Some(kind) = single {
+            chars.next();
+            tokens.push(Token { kind, span: Span { start, end: start + c.len_utf8() } });
+            continue;
+        }
+
+        let mut end = start;
+        let mut depth = 0;
+        let mut quote: Option<(char, usize)> = None;
+        let mut prev: Option<char> = None;
+
+        // Where the outermost `(` of an argument group is
+        let mut group_start = start;
+
+        while let Some(&(i, c)) = chars.peek() {
+            // Quotes only open a value, so `has:it's` is a plain word
+            let opens_value = prev.is_none_or(|p| matches!(p, ':' | '(' | ',') || p.is_whitespace());
+
+            match (quote, c) {
+                // A `/` can be escaped inside a regex
+                (Some(('/', _)), '/') if prev == Some('\\') => {}
+                (Some((open, _)), _) if c == open => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') if opens_value => quote = Some((c, i)),
+                // A /regex/ is quoted too, so `|` and spaces in it stay in the term. Paths aren't
+                (None, '/') if opens_value && closes_regex(q, i) => quote = Some((c, i)),
+                (None, '(') => {
+                    if depth == 0 {
+                        group_start = i;
+                    }
+                    depth += 1;
+                }
+                (None, ')') if depth > 0 => depth -= 1,
+                // The `)` closes a sub-expression, not part of this term
+                (None, ')') => break,
+                (None, '|') if depth == 0 => break,
+                (None, _) if c.is_whitespace() && depth == 0 => break,
+                _ => {}
+            }
+
+            end = i + c.len_utf8();
+            prev = Some(c);
+            chars.next();
+        }
+
+        let text = &q[start..end];
+        if let Some((open, at)) = quote {
+            return Err(ParseError::new(format!("Unclosed {}", open), Span { start: at, end })
+                .expected(format!("a closing {}", open)));
+        }
+        if depth > 0 {
+            return Err(ParseError::new("Unclosed (", Span { start: group_start, end })
+                .expected("a closing )"));
+        }
+
+        let kind = match text {
+            "AND" => TokenKind::And,
+            "OR" => TokenKind::Or,
+            "NOT" => TokenKind::Not,
+            _ => TokenKind::Term(text.to_string()),
+        };
+        tokens.push(Token { kind, span: Span { start, end } });
+    }
+
+    Ok(tokens)
+}
+
+/// What makes `/…/` a regex rather than a path. Not `.`, too many names have one
+const REGEX_SYNTAX: &[char] = &['\\', '^', '$', '*', '+', '?', '(', ')', '[', ']', '{', '}', '|'];
+
+/// Where the `/` that closes a regex is in `rest`, what follows the opening one. Inside, a `/` is
+/// escaped by a `\` right before
+fn closing_slash(rest: &str) -> Option<usize> {
+    let mut prev = None;
+    rest.char_indices().find_map(|(i, c)| {
+        let found = c == '/' && prev != Some('\\');
+        prev = Some(c);
+        found.then_some(i)
+    })
+}
+
+/// `raw` as the pattern and flags of a regex, when all of it is `/pattern/flags` with flags among
+/// i, m, s, x and U. Unless it's `explicit`, written as `re:/pattern/`, the pattern has to use some
+/// regex syntax, so paths like `/home/`, `/a.b/` or `/a/b/mix` aren't regexes
+pub(crate) fn split_regex(raw: &str, explicit: bool) -> Option<(&str, &str)> {
+    let rest = raw.strip_prefix('/')?;
+    let close = closing_slash(rest)?;
+    let (pattern, flags) = (&rest[..close], &rest[close + 1..]);
+
+    let syntax = explicit || pattern.contains(REGEX_SYNTAX);
+    (!pattern.is_empty() && syntax && flags.chars().all(|c| "imsxU".contains(c))).then_some((pattern, flags))
+}
+
+/// Whether the `/` at `at` opens a regex that closes, flags and all, where the term would end.
+/// So in `has:/a b|c/i` the space and `|` stay in the term, while in `parent:/home name:a/b`
+/// the `/` of `a/b` doesn't pull `name:` in
+fn closes_regex(q: &str, at: usize) -> bool {
+    let rest = &q[at + 1..];
+    let Some(close) = closing_slash(rest) else {
+        return false;
+    };
+
+    let end = rest[close + 1..].find(|c: char| !"imsxU".contains(c)).map_or(rest.len(), |n| close + 1 + n);
+    let ends_term = |c: char| c.is_whitespace() || matches!(c, '|' | ')' | ',');
+    if !rest[end..].chars().next().is_none_or(ends_term) {
+        return false;
+    }
+
+    split_regex(&q[at..at + 1 + end], q[..at].ends_with(":re:")).is_some()
+}
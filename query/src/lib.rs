@@ -0,0 +1,667 @@
+//! The search language shared by the app and the `fstree` CLI.
+//!
+//! ```
+//! use query::Query;
+//!
+//! let q = Query::parse("has:report size:>1kb");
+//! assert!(q.errors().is_empty());
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use fs_tree_db::FileMeta;
+use rayon::prelude::*;
+use serde::{ Deserialize, Serialize };
+
+use content::LineSink;
+
+pub mod parser;
+pub use parser::{ Parser, PREDICATES };
+
+pub mod types;
+pub use types::{ Arg, Candidate, Expr, Score };
+
+pub mod lexer;
+pub use lexer::Span;
+
+pub mod error;
+pub use error::ParseError;
+
+pub mod dates;
+pub use dates::DateOrder;
+
+pub mod sizes;
+pub use sizes::{ format_size, SizeUnits };
+
+pub mod text;
+pub use text::{ CaseMode, Normalization };
+
+pub mod filetypes;
+
+pub mod content;
+pub use content::{ Encoding, LineMatch };
+
+mod functions;
+mod fuzzy;
+mod macros;
+
+/// Files opened at once by `Query::for_each_match`, results of a batch come when all of it is read
+pub const CONTENT_BATCH: usize = 256;
+
+/// A filter function the language knows about, for help text and completion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Predicate {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+pub fn predicates() -> &'static [Predicate] {
+    PREDICATES
+}
+
+/// Settings that change how values in a query are read, mirrored by the app's settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParseOptions {
+    pub date_order: DateOrder,
+    pub size_units: SizeUnits,
+
+    /// For the text filters: `has`, `name` and `parent`
+    pub case: CaseMode,
+    pub normalization: Normalization,
+
+    /// `resume` matches `résumé`
+    pub fold_diacritics: bool,
+
+    /// Groups for `type:`, as extensions. They're added to the built-in ones, or replace
+    /// one with the same name
+    pub file_types: BTreeMap<String, Vec<String>>,
+
+    /// How `contains:` decodes files
+    pub encoding: Encoding,
+
+    /// Bytes `contains:` reads per file at most
+    pub read_limit: u64,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            date_order: DateOrder::default(),
+            size_units: SizeUnits::default(),
+            case: CaseMode::default(),
+            normalization: Normalization::default(),
+            fold_diacritics: false,
+            file_types: BTreeMap::new(),
+            encoding: Encoding::default(),
+            read_limit: content::DEFAULT_READ_LIMIT,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// How text filters prepare paths, `lowercase` when case is ignored by comparing lowercase
+    pub fn folding(&self, lowercase: bool) -> text::Folding {
+        text::Folding { lowercase, normalization: self.normalization, diacritics: self.fold_diacritics }
+    }
+}
+
+/// A parsed query, see `Parser` for the grammar
+#[derive(Debug, Clone)]
+pub struct Query {
+    expr: Option<Expr>,
+    errors: Vec<ParseError>,
+}
+
+impl Query {
+    /// Terms that don't parse are left out and listed in `errors`, so a query
+    /// being typed still matches on the terms that are already complete
+    pub fn parse(q: &str) -> Self {
+        Self::parse_with(q, &ParseOptions::default())
+    }
+
+    pub fn parse_with(q: &str, options: &ParseOptions) -> Self {
+        let (expr, errors) = Parser::parse(q, options);
+        Query { expr, errors }
+    }
+
+    pub fn expr(&self) -> Option<&Expr> {
+        self.expr.as_ref()
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// An empty query matches everything
+    pub fn is_empty(&self) -> bool {
+        self.expr.is_none()
+    }
+
+    pub fn matches(&self, candidate: &Candidate) -> bool {
+        self.expr.as_ref().is_none_or(|e| e.matches(candidate))
+    }
+
+    /// For a file from an index, `meta` is what was cached for it
+    pub fn matches_entry(&self, path: &Path, meta: Option<&FileMeta>) -> bool {
+        self.matches(&Candidate { path: &path.to_string_lossy(), meta, lines: None })
+    }
+
+    /// For a file on disk, it's stat'ed if a filter needs its metadata
+    pub fn matches_path(&self, path: &Path) -> bool {
+        self.matches_entry(path, None)
+    }
+
+    /// Whether results are better shown best first, like for `fuzzy:`
+    pub fn is_ranked(&self) -> bool {
+        self.expr.as_ref().is_some_and(Expr::ranks)
+    }
+
+    /// How well an entry matched, see `Expr::score`. Everything scores 0 for an empty query,
+    /// and for queries that aren't ranked
+    pub fn score(&self, candidate: &Candidate) -> Score {
+        match &self.expr {
+            Some(e) if e.ranks() => e.score(candidate),
+            // Same result, but OR stops at the first part that matches
+            _ => self.matches(candidate).then_some(0),
+        }
+    }
+
+    pub fn score_entry(&self, path: &Path, meta: Option<&FileMeta>) -> Score {
+        self.score(&Candidate { path: &path.to_string_lossy(), meta, lines: None })
+    }
+
+    /// Whether files are opened to match it, like for `contains:`. Those are better
+    /// matched a batch at a time with `for_each_match`
+    pub fn reads_content(&self) -> bool {
+        self.expr.as_ref().is_some_and(Expr::reads_content)
+    }
+
+    /// False when the path and metadata filters already rule the entry out,
+    /// without opening it
+    pub fn could_match_entry(&self, path: &Path, meta: Option<&FileMeta>) -> bool {
+        let candidate = Candidate { path: &path.to_string_lossy(), meta, lines: None };
+        self.expr.as_ref().is_none_or(|e| e.prefilter(&candidate) != Some(false))
+    }
+
+    /// Calls `found` with each entry that matches, its score and up to `max_lines` lines
+    /// `contains:` found in it, until it returns false.
+    /// Queries that read files rule out what they can from paths and metadata first,
+    /// then open the rest `CONTENT_BATCH` at a time on all cores, keeping their order
+    pub fn for_each_match<'a, P, I, F>(&self, entries: I, max_lines: usize, mut found: F)
+        where
+            I: IntoIterator<Item = (P, Option<&'a FileMeta>)>,
+            P: AsRef<Path> + Sync,
+            F: FnMut(P, Option<&'a FileMeta>, u32, Vec<LineMatch>) -> bool
+    {
+        if !self.reads_content() {
+            for (path, meta) in entries {
+                if let Some(score) = self.score_entry(path.as_ref(), meta) {
+                    if !found(path, meta, score, Vec::new()) {
+                        return;
+                    }
+                }
+            }
+            return;
+        }
+
+        let mut entries = entries.into_iter().filter(|(path, meta)| self.could_match_entry(path.as_ref(), *meta));
+        let mut batch = Vec::with_capacity(CONTENT_BATCH);
+        loop {
+            batch.extend(entries.by_ref().take(CONTENT_BATCH));
+            if batch.is_empty() {
+                return;
+            }
+
+            let matches: Vec<_> = batch
+                .par_iter()
+                .map(|(path, meta)| self.score_with_lines(path.as_ref(), *meta, max_lines))
+                .collect();
+            for ((path, meta), matched) in batch.drain(..).zip(matches) {
+                if let Some((score, lines)) = matched {
+                    if !found(path, meta, score, lines) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// `score_entry`, keeping the lines `contains:` found on the way
+    fn score_with_lines(&self, path: &Path, meta: Option<&FileMeta>, max_lines: usize) -> Option<(u32, Vec<LineMatch>)> {
+        let sink = LineSink::new(max_lines);
+        let score = self.score(&Candidate { path: &path.to_string_lossy(), meta, lines: Some(&sink) })?;
+        Some((score, sink.into_lines()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use fs_tree_db::FileKind;
+
+    fn file(size: u64) -> FileMeta {
+        FileMeta { size, modified: None, changed: None, mode: 0o644, kind: FileKind::File, inode: None }
+    }
+
+    #[test]
+    fn queries_match_indexed_entries() {
+        let q = Query::parse("has:report size:>1kb");
+        assert!(q.errors().is_empty());
+
+        let path = Path::new("/home/a/report.pdf");
+        assert!(q.matches_entry(path, Some(&file(2_000))));
+        assert!(!q.matches_entry(path, Some(&file(10))));
+        assert!(!q.matches_entry(Path::new("/home/a/notes.pdf"), Some(&file(2_000))));
+
+        // Bad terms are reported, the rest still applies
+        let q = Query::parse("has:report nope:1 modified:>10mb");
+        assert_eq!(q.errors().len(), 2);
+        assert!(q.matches_entry(path, Some(&file(10))));
+
+        assert!(predicates().iter().any(|p| p.name == "modified"));
+    }
+
+    #[test]
+    fn boolean_operators_group_and_negate() {
+        let small = file(10);
+        let matching = |q: &str| -> Vec<&str> {
+            let q = Query::parse(q);
+            assert!(q.errors().is_empty(), "{:?}", q.errors());
+
+            ["/a/report.pdf", "/a/invoice.pdf", "/a/my notes.txt", "/b/report.txt"]
+                .into_iter()
+                .filter(|p| q.matches_entry(Path::new(p), Some(&small)))
+                .collect()
+        };
+
+        assert_eq!(matching("has:report OR has:invoice"), ["/a/report.pdf", "/a/invoice.pdf", "/b/report.txt"]);
+        assert_eq!(matching("has:pdf -has:report"), ["/a/invoice.pdf"]);
+        assert_eq!(matching("NOT has:a/ AND has:report"), ["/b/report.txt"]);
+        assert_eq!(matching("has:a/ (has:report | has:notes)"), ["/a/report.pdf", "/a/my notes.txt"]);
+        assert_eq!(matching(r#"has:"my notes""#), ["/a/my notes.txt"]);
+        assert_eq!(matching("has:txt size:(>1b, <1kb)"), ["/a/my notes.txt", "/b/report.txt"]);
+
+        // Syntax errors fail the whole query
+        for bad in ["(has:a", "has:a)", "has:a OR", "OR has:a", "has:\"a", "-"] {
+            let q = Query::parse(bad);
+            assert!(q.is_empty() && q.errors().len() == 1, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let error = |q: &str| Query::parse(q).errors()[0].clone();
+        let text = |q: &str| {
+            let e = error(q);
+            q[e.span.start..e.span.end].to_string()
+        };
+
+        let typo = error("has:a sise:>1mb");
+        assert_eq!(typo.span, Span { start: 6, end: 10 });
+        assert_eq!(typo.suggestion.as_deref(), Some("size"));
+        assert_eq!(typo.to_string(), "Unknown filter 'sise', did you mean `size`?");
+        assert_eq!(error("modifed:>2024-01-01").suggestion.as_deref(), Some("modified"));
+        assert_eq!(error("zzzz:1").suggestion, None);
+
+        assert_eq!(text("size:>1zb"), ">1zb");
+        assert_eq!(text("has:a OR"), "OR");
+        assert_eq!(text("(has:a has:b"), "(");
+        assert_eq!(text("has:a)"), ")");
+        assert_eq!(text(r#"has:"my report"#), r#""my report"#);
+        assert_eq!(error("report").suggestion.as_deref(), Some("has:report"));
+        assert_eq!(error("(has:a").expected.as_deref(), Some(")"));
+    }
+
+    #[test]
+    fn regexes_end_with_their_term() {
+        let terms = |q: &str| -> Vec<String> {
+            lexer::tokenize(q)
+                .unwrap()
+                .into_iter()
+                .filter_map(|t| match t.kind {
+                    lexer::TokenKind::Term(text) => Some(text),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(terms("parent:/home name:a/b"), ["parent:/home", "name:a/b"]);
+        assert_eq!(terms("has:/home/docs has:x/y"), ["has:/home/docs", "has:x/y"]);
+        assert_eq!(terms("has:/a b|c/i size:>1kb"), ["has:/a b|c/i", "size:>1kb"]);
+        assert_eq!(terms("has:(/a b/, /c/) name:x"), ["has:(/a b/, /c/)", "name:x"]);
+        assert_eq!(Query::parse("parent:/home name:a/b").errors(), []);
+
+        // Without regex syntax it's a path, unless it's marked with re:
+        assert_eq!(terms("has:/a b/"), ["has:/a", "b/"]);
+        assert_eq!(terms("has:re:/a b/ name:x"), ["has:re:/a b/", "name:x"]);
+    }
+
+    #[test]
+    fn comparisons_and_ranges() {
+        let day = |d: &str| {
+            let date = chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+            let time = date.and_hms_opt(12, 0, 0).unwrap().and_utc();
+            FileMeta { modified: Some(time.into()), ..file(0) }
+        };
+        let matches = |q: &str, meta: &FileMeta| {
+            let q = Query::parse(q);
+            assert!(q.errors().is_empty(), "{:?}", q.errors());
+            q.matches_entry(Path::new("/a"), Some(meta))
+        };
+
+        assert!(matches("size:=0", &file(0)) && matches("size:0", &file(0)));
+        assert!(!matches("size:!=0", &file(0)) && matches("size:!=0", &file(1)));
+        assert!(matches("size:>=1kb", &file(1_000)) && !matches("size:>1kb", &file(1_000)));
+        assert!(matches("size:<=1kb", &file(1_000)) && !matches("size:<1kb", &file(1_000)));
+        assert!(matches("size:10kb..2mb", &file(2_000_000)) && !matches("size:10kb..2mb", &file(9_999)));
+        assert!(!matches("size:(0, 1b)", &file(1)));
+
+        assert!(matches("modified:2024-01-01", &day("2024-01-01")));
+        assert!(!matches("modified:2024-01-01", &day("2024-01-02")));
+        assert!(matches("modified:2024-01..2024-03", &day("2024-03-31")));
+        assert!(matches("modified:2024", &day("2024-12-31")) && !matches("modified:2024", &day("2025-01-01")));
+        assert!(matches("modified:>=2023", &day("2023-01-01")) && !matches("modified:2023..2023", &day("2024-01-01")));
+        assert!(matches("size:2024", &file(2024)));
+        assert!(!matches("modified:2024-01..2024-03", &day("2024-04-01")));
+        assert!(matches("modified:>2024-01", &day("2024-02-01")) && !matches("modified:>2024-01", &day("2024-01-31")));
+        assert!(matches("modified:<=2024-02", &day("2024-02-29")));
+        assert!(matches("modified:!=2024-01", &day("2023-12-31")));
+
+        // `..` in a plain word is still text, and only sizes and dates have ranges
+        assert!(Query::parse("has:a..b").errors().is_empty());
+        let q = Query::parse("has:v1..2");
+        assert!(q.errors().is_empty(), "{:?}", q.errors());
+        assert!(q.matches_entry(Path::new("/notes/v1..2.txt"), None));
+        for bad in ["size:2mb..10kb", "size:1kb..2024-01", "size:>=2024-01-01", "modified:0", "size:10kb..2zb"] {
+            assert_eq!(Query::parse(bad).errors().len(), 1, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn relative_dates_and_times() {
+        let at = |time: chrono::NaiveDateTime| {
+            let time = time.and_local_timezone(chrono::Local).earliest().unwrap();
+            FileMeta { modified: Some(time.into()), ..file(0) }
+        };
+        let matches = |q: &str, options: &ParseOptions, meta: &FileMeta| {
+            let q = Query::parse_with(q, options);
+            assert!(q.errors().is_empty(), "{:?}", q.errors());
+            q.matches_entry(Path::new("/a"), Some(meta))
+        };
+        let defaults = ParseOptions::default();
+        let check = |q: &str, meta: &FileMeta| matches(q, &defaults, meta);
+
+        let now = dates::now();
+        let days_ago = |days| at(now - chrono::TimeDelta::days(days));
+
+        assert!(check("modified:<7d", &days_ago(1)) && !check("modified:<7d", &days_ago(8)));
+        assert!(check("modified:>2w", &days_ago(15)) && !check("modified:>2w", &days_ago(13)));
+        assert!(check("modified:3d..1w", &days_ago(5)) && !check("modified:1w..3d", &days_ago(2)));
+        assert!(check("modified:today", &days_ago(0)) && !check("modified:today", &days_ago(1)));
+        assert!(check("modified:yesterday", &days_ago(1)));
+        assert!(check("modified:thismonth", &days_ago(0)) && check("modified:>=thisyear", &days_ago(0)));
+
+        let minute = chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap().and_hms_opt(14, 30, 20).unwrap();
+        assert!(check(r#"modified:"2024-01-31 14:30""#, &at(minute)));
+        assert!(check("modified:2024-01-31T14:30:20", &at(minute)));
+        assert!(!check("modified:>=2024-01-31T14:31", &at(minute)));
+
+        // 03/04 is the 3rd of April unless months come first
+        let april = at(chrono::NaiveDate::from_ymd_opt(2024, 4, 3).unwrap().and_hms_opt(9, 0, 0).unwrap());
+        let month_first = ParseOptions { date_order: DateOrder::MonthFirst, ..Default::default() };
+        assert!(check("modified:03/04/2024", &april));
+        assert!(!matches("modified:03/04/2024", &month_first, &april));
+        assert!(matches("modified:04/03/2024", &month_first, &april));
+        assert_eq!(Query::parse_with("modified:31/12/2023", &month_first).errors().len(), 1);
+    }
+
+    #[test]
+    fn regexes_globs_and_names() {
+        let matching = |q: &str| -> Vec<&str> {
+            let q = Query::parse(q);
+            assert!(q.errors().is_empty(), "{:?}", q.errors());
+
+            ["/src/main.rs", "/src/tests/a.py", "/docs/tests/b.py", "/docs/README.md", "/docs/v2/readme.txt"]
+                .into_iter()
+                .filter(|p| q.matches_entry(Path::new(p), Some(&file(1))))
+                .collect()
+        };
+
+        assert_eq!(matching("case:has:/read|main/"), ["/src/main.rs", "/docs/v2/readme.txt"]);
+        assert_eq!(matching(r"has:/^\/docs\/.*readme/i"), ["/docs/README.md", "/docs/v2/readme.txt"]);
+        assert_eq!(matching("has:*.py"), ["/src/tests/a.py", "/docs/tests/b.py"]);
+        assert_eq!(matching("has:src/**/*.py"), ["/src/tests/a.py"]);
+        assert_eq!(matching("has:/docs/*.md"), ["/docs/README.md"]);
+        assert_eq!(matching("name:/^readme/i"), ["/docs/README.md", "/docs/v2/readme.txt"]);
+        assert_eq!(matching("name:s"), ["/src/main.rs"]);
+        assert_eq!(matching("parent:tests"), ["/src/tests/a.py", "/docs/tests/b.py"]);
+        assert_eq!(matching("parent:v?"), ["/docs/v2/readme.txt"]);
+        assert_eq!(matching(r#"has:"/docs/v2""#), ["/docs/v2/readme.txt"]);
+
+        for bad in ["has:/a(/", "name:[a", "name:src/*.rs", "has:/a/ /(/"] {
+            assert_eq!(Query::parse(bad).errors().len(), 1, "{}", bad);
+        }
+
+        let bad = "has:a has:/a(b/i";
+        let e = Query::parse(bad).errors()[0].clone();
+        assert_eq!(&bad[e.span.start..e.span.end], "/a(b/i");
+        assert!(e.message.starts_with("Invalid regex"), "{}", e.message);
+    }
+
+    #[test]
+    fn absolute_paths_stay_paths() {
+        let paths = ["/a/b/mix/c.txt", "/a/b/c.txt", "/home/x.txt", "/x/homework.txt", "/a.b/c.txt", "/aXb/c.txt"];
+        let matching = |q: &str| -> Vec<&str> {
+            let q = Query::parse(q);
+            assert!(q.errors().is_empty(), "{:?}", q.errors());
+
+            paths.into_iter().filter(|p| q.matches_entry(Path::new(p), Some(&file(1)))).collect()
+        };
+
+        assert_eq!(matching("parent:/a/b/mix"), ["/a/b/mix/c.txt"]);
+        assert_eq!(matching("has:/home/"), ["/home/x.txt"]);
+        assert_eq!(matching("has:/a.b/"), ["/a.b/c.txt"]);
+
+        // Unless it's marked as a regex
+        assert_eq!(matching("has:re:/home/"), ["/home/x.txt", "/x/homework.txt"]);
+        assert_eq!(matching(r"has:/^\/a\/b\//"), ["/a/b/mix/c.txt", "/a/b/c.txt"]);
+        assert_eq!(Query::parse("has:re:/a/b/").errors().len(), 1);
+    }
+
+    #[test]
+    fn case_modes_and_unicode_folding() {
+        let matches = |q: &str, options: &ParseOptions, path: &str| {
+            let q = Query::parse_with(q, options);
+            assert!(q.errors().is_empty(), "{:?}", q.errors());
+            q.matches_entry(Path::new(path), Some(&file(1)))
+        };
+        let defaults = ParseOptions::default();
+        let check = |q: &str, path: &str| matches(q, &defaults, path);
+
+        // Smart case by default
+        assert!(check("has:report", "/a/Report.pdf"));
+        assert!(!check("has:Report", "/a/report.pdf"));
+        assert!(check("name:*.PDF", "/a/report.PDF") && !check("name:*.PDF", "/a/report.pdf"));
+        assert!(check(r"name:/\d+\.txt/", "/a/NOTES2.txt"));
+
+        // Per term
+        assert!(!check("case:has:report", "/a/Report.pdf"));
+        assert!(check("nocase:has:REPORT", "/a/report.pdf"));
+        assert!(check("nocase:name:*.PDF", "/a/report.pdf"));
+
+        let sensitive = ParseOptions { case: CaseMode::Sensitive, ..Default::default() };
+        assert!(!matches("has:report", &sensitive, "/a/Report.pdf"));
+        assert!(matches("smartcase:has:report", &sensitive, "/a/Report.pdf"));
+
+        // `e` and a combining accent against a precomposed `é`, and the other way around
+        let decomposed = "/a/Re\u{301}sume\u{301}.pdf";
+        assert!(check("has:Résumé", decomposed));
+        assert!(check("name:/^résumé/i", decomposed));
+        assert!(check("has:Re\u{301}sume\u{301}", "/a/Résumé.pdf"));
+        assert!(!check("has:resume", "/a/Résumé.pdf"));
+
+        let unnormalized = ParseOptions { normalization: Normalization::None, ..Default::default() };
+        assert!(!matches("has:Résumé", &unnormalized, decomposed));
+
+        let folded = ParseOptions { fold_diacritics: true, ..Default::default() };
+        assert!(matches("has:resume", &folded, "/a/Résumé.pdf"));
+        assert!(matches("name:r*sume.pdf", &folded, decomposed));
+
+        let compatible = ParseOptions { normalization: Normalization::Nfkc, ..Default::default() };
+        assert!(matches("has:file", &compatible, "/a/\u{FB01}le.txt") && !check("has:file", "/a/\u{FB01}le.txt"));
+    }
+
+    #[test]
+    fn fuzzy_scores_rank_matches() {
+        let ranked = |q: &str, paths: &[&'static str]| -> Vec<&'static str> {
+            let q = Query::parse(q);
+            assert!(q.errors().is_empty() && q.is_ranked(), "{:?}", q.errors());
+
+            let mut scored: Vec<(u32, &str)> = paths
+                .iter()
+                .filter_map(|p| Some((q.score_entry(Path::new(p), Some(&file(1)))?, *p)))
+                .collect();
+            scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+            scored.into_iter().map(|(_, p)| p).collect()
+        };
+
+        let paths = ["/x/my_driver.rs", "/x/main_driver.rs", "/x/mdrs", "/x/m-d-r-s.txt", "/x/readme.md"];
+        assert_eq!(ranked("fuzzy:mdrs", &paths), ["/x/mdrs", "/x/m-d-r-s.txt", "/x/my_driver.rs", "/x/main_driver.rs"]);
+
+        // Word starts and camel humps beat letters in the middle of words
+        assert_eq!(ranked("fuzzy:fb", &["/x/afoob.txt", "/x/fooBar.txt", "/x/foo_bar.txt"])[2], "/x/afoob.txt");
+        assert_eq!(ranked("fuzzy:fb", &["/x/foobar.txt", "/x/fooBar.txt"]), ["/x/fooBar.txt", "/x/foobar.txt"]);
+        assert_eq!(ranked("fuzzy:x/mdr", &paths)[0], "/x/mdrs");
+        assert_eq!(ranked("fuzzy:x/mdr", &paths).len(), 4);
+
+        // Other terms filter without changing the order, NOT doesn't rank
+        assert_eq!(ranked("fuzzy:mdrs has:.rs", &paths), ["/x/my_driver.rs", "/x/main_driver.rs"]);
+        assert!(!Query::parse("-fuzzy:mdrs").is_ranked() && !Query::parse("has:a").is_ranked());
+        assert_eq!(Query::parse("fuzzy:/a|b/").errors().len(), 1);
+    }
+
+    #[test]
+    fn extensions_and_file_types() {
+        let dir = FileMeta { kind: FileKind::Dir, ..file(0) };
+        let matching = |q: &str, options: &ParseOptions| -> Vec<&str> {
+            let q = Query::parse_with(q, options);
+            assert!(q.errors().is_empty(), "{:?}", q.errors());
+
+            ["/a/main.rs", "/a/main.rs.bak", "/a/Photo.JPG", "/a/x.png", "/a/b.tar.gz", "/a/.rs", "/a/notes.md"]
+                .into_iter()
+                .filter(|p| q.matches_entry(Path::new(p), Some(&file(1))))
+                .collect()
+        };
+        let defaults = ParseOptions::default();
+
+        assert_eq!(matching("ext:rs", &defaults), ["/a/main.rs"]);
+        assert_eq!(matching("ext:.jpg,PNG", &defaults), ["/a/Photo.JPG", "/a/x.png"]);
+        assert_eq!(matching("ext:(md, gz)", &defaults), ["/a/b.tar.gz", "/a/notes.md"]);
+        assert_eq!(matching("ext:tar.gz", &defaults), ["/a/b.tar.gz"]);
+        assert_eq!(matching("type:image", &defaults), ["/a/Photo.JPG", "/a/x.png"]);
+        assert_eq!(matching("type:archive,document", &defaults), ["/a/b.tar.gz", "/a/notes.md"]);
+        assert!(!Query::parse("ext:rs").matches_entry(Path::new("/a/src.rs"), Some(&dir)));
+
+        // Groups from the settings, new or replacing a built-in one
+        let mut options = ParseOptions::default();
+        options.file_types.insert("backup".to_string(), vec![".bak".to_string()]);
+        options.file_types.insert("image".to_string(), vec!["png".to_string()]);
+        assert_eq!(matching("type:backup", &options), ["/a/main.rs.bak"]);
+        assert_eq!(matching("type:image", &options), ["/a/x.png"]);
+
+        let error = Query::parse("type:imgae").errors()[0].clone();
+        assert_eq!(error.suggestion.as_deref(), Some("image"));
+        assert_eq!(Query::parse("ext:/a|b/").errors().len(), 1);
+        assert_eq!(Query::parse("ext:,").errors().len(), 1);
+    }
+
+    #[test]
+    fn contains_reads_text_files() {
+        let dir = std::env::temp_dir().join(format!("query_contains_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, bytes: &[u8]| {
+            let path = dir.join(name);
+            std::fs::write(&path, bytes).unwrap();
+            path
+        };
+
+        let notes = write("notes.txt", b"first\r\n  TODO: call Bob  \nnothing\ntodo later\n");
+        let binary = write("blob.bin", b"TODO\0\x01\x02");
+        let wide = write("wide.txt", &[&[0xFF, 0xFE][..], &"x\nTODO\n".encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<_>>()].concat());
+        let latin = write("latin.txt", b"caf\xe9\n");
+        let matching = |q: &str, options: &ParseOptions| -> Vec<String> {
+            let q = Query::parse_with(q, options);
+            assert!(q.errors().is_empty() && q.reads_content(), "{:?}", q.errors());
+
+            let entries: Vec<(PathBuf, Option<&FileMeta>)> =
+                [&notes, &binary, &wide, &latin].into_iter().map(|p| (p.clone(), None)).collect();
+            let mut found = Vec::new();
+            q.for_each_match(entries, 3, |path, _, _, _| {
+                found.push(path.file_name().unwrap().to_string_lossy().to_string());
+                true
+            });
+            found
+        };
+        let defaults = ParseOptions::default();
+
+        assert_eq!(matching("contains:TODO", &defaults), ["notes.txt", "wide.txt"]);
+        assert_eq!(matching("contains:todo", &defaults), ["notes.txt", "wide.txt"]);
+        assert_eq!(matching("case:contains:/^todo/", &defaults), ["notes.txt"]);
+        assert_eq!(matching("contains:todo name:*.bin", &defaults), Vec::<String>::new());
+        assert_eq!(matching("contains:café", &ParseOptions { encoding: Encoding::Latin1, ..defaults.clone() }), ["latin.txt"]);
+        assert_eq!(matching("contains:later", &ParseOptions { read_limit: 20, ..defaults.clone() }), Vec::<String>::new());
+
+        // Lines come from the same read that matched, not from a NOT
+        let lines = |q: &str| {
+            let mut lines = Vec::new();
+            Query::parse(q).for_each_match([(notes.clone(), None)], 3, |_, _, _, found| {
+                lines = found;
+                true
+            });
+            lines
+        };
+        assert_eq!(lines("contains:todo"), [LineMatch { line: 2, text: "TODO: call Bob".into() }, LineMatch { line: 4, text: "todo later".into() }]);
+        assert_eq!(lines("contains:(todo, later)"), [LineMatch { line: 2, text: "TODO: call Bob".into() }, LineMatch { line: 4, text: "todo later".into() }]);
+        assert_eq!(lines("contains:first -contains:zzz"), [LineMatch { line: 1, text: "first".into() }]);
+        assert_eq!(lines("name:notes.txt -contains:zzz"), []);
+
+        // Files the name rules out aren't opened, and the cheap part goes first
+        let q = Query::parse("contains:x name:*.rs");
+        assert!(!q.could_match_entry(&notes, None));
+        assert!(matches!(q.expr(), Some(Expr::And(parts)) if !parts[0].reads_content()));
+        assert!(Query::parse("contains:x OR name:*.txt").could_match_entry(&notes, None));
+        assert_eq!(Query::parse("contains:*.rs").errors().len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sizes_take_decimals_and_binary_units() {
+        let decimal = SizeUnits::Decimal;
+        let binary = SizeUnits::Binary;
+
+        assert_eq!(sizes::parse_size("1.5gb", decimal), Ok(1_500_000_000));
+        assert_eq!(sizes::parse_size("1.5GB", binary), Ok(1_610_612_736));
+        assert_eq!(sizes::parse_size("2KiB", decimal), Ok(2_048));
+        assert_eq!(sizes::parse_size("3tb", decimal), Ok(3_000_000_000_000));
+        assert_eq!(sizes::parse_size("1pb", binary), Ok(1 << 50));
+        assert_eq!(sizes::parse_size("200", decimal), Ok(200));
+        assert!(sizes::parse_size("20000pb", decimal).is_err());
+        assert!(sizes::parse_size("1.2.3mb", decimal).is_err());
+
+        assert_eq!(format_size(1_500_000, decimal), "1.5 MB");
+        assert_eq!(format_size(1_572_864, binary), "1.5 MiB");
+        assert_eq!(format_size(999, decimal), "999 B");
+
+        // What's shown reads back as about the same size
+        let shown = format_size(1_610_612_736, binary);
+        assert_eq!(sizes::parse_size(&shown, decimal), Ok(1_610_612_736));
+
+        let kib = ParseOptions { size_units: binary, ..Default::default() };
+        let q = Query::parse_with("size:1kb", &kib);
+        assert!(q.matches_entry(Path::new("/a"), Some(&file(1_024))));
+    }
+}
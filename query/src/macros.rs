@@ -0,0 +1,39 @@
+#[macro_export]
+/// Creates a map of `function_name`:`function pointer` and the list of `Predicate`s,
+/// given each function's name and what it does
+/// Ex. `define_functions!(foo: "Foo things", bar as "baz": "Bar things")`, `as` names a
+/// function that can't be called what the filter is, like `type`
+/// Translates to
+/// ```text
+/// FUNCTION_MAP = {
+///     function_name: <pointer to function>, *
+/// }
+/// PREDICATES = [ Predicate { name, description }, * ]
+/// ```
+macro_rules! define_functions {
+    (@name $fn_name:ident) => { stringify!($fn_name) };
+    (@name $fn_name:ident $alias:literal) => { $alias };
+
+    ( $( $fn_name:ident $( as $alias:literal )? : $description:literal ),* $(,)? ) => {
+        $(
+            use $crate::functions::$fn_name;
+        )*
+
+
+        lazy_static::lazy_static! {
+            pub static ref FUNCTION_MAP: std::collections::HashMap<String, $crate::types::FunctionTy> = {
+                let mut m = std::collections::HashMap::new();
+                $(
+                    m.insert($crate::define_functions!(@name $fn_name $($alias)?).to_string(), $fn_name as $crate::types::FunctionTy);
+                )*
+                m
+            };
+        }
+
+        pub static PREDICATES: &[$crate::Predicate] = &[
+            $(
+                $crate::Predicate { name: $crate::define_functions!(@name $fn_name $($alias)?), description: $description },
+            )*
+        ];
+    };
+}
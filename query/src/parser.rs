@@ -0,0 +1,600 @@
+use std::borrow::Cow;
+
+use globset::GlobBuilder;
+
+use crate::content::ContentSearch;
+use crate::error::{ did_you_mean, ParseError };
+use crate::lexer::{ split_regex, tokenize, Span, Token, TokenKind };
+use crate::text::CaseMode;
+use crate::types::{ Arg, Candidate, Expr, Operator, Pattern, PatternKind, Unit };
+use crate::{ dates, define_functions, filetypes, sizes, ParseOptions };
+
+// Creates a map `FUNCTION_MAP`, containing `name`:`function`, and the `PREDICATES` list
+// Filter function definition.
+define_functions!(
+    has: "Full path contains the text, or matches a glob or /regex/flags. has:report, has:**/tests/*.py, has:/v\\d+/i",
+    name: "File or folder name contains the text, or matches a glob or /regex/flags. name:*.rs, name:/^readme/i",
+    parent: "Folder the file is in contains the text, or matches a glob or /regex/flags. parent:src, parent:**/tests",
+    ext: "File extension, any case, one or several. ext:rs, ext:jpg,png, ext:tar.gz",
+    file_type as "type": "Files of a kind, by extension: archive, audio, code, document, font, image, presentation, spreadsheet, video or one from the settings. type:image, type:(audio, video)",
+    fuzzy: "Name has the letters in order, best matches first. fuzzy:mdrs finds main_driver.rs, with a / the path is used",
+    size: "File size in b, kb to pb or kib to pib, compared with < <= = != >= > or a range. size:>=1.5gb, size:0, size:10kb..2mb",
+    contains: "A line of the file has the text or matches a /regex/flags, binary files are skipped. contains:TODO, contains:/fn \\w+_test/",
+    modified: "Last modified date or age. modified:>2024-01-01, modified:2024, modified:2024-01..2024-03, modified:<7d, modified:yesterday, modified:>=\"2024-01-31 14:30\"",
+);
+
+/// Filters whose scores differ between matches, see `Expr::ranks`
+const RANKING: &[&str] = &["fuzzy"];
+
+/// Filters that take a `from..to` range, for the rest `..` is just text
+const RANGES: &[&str] = &["size", "modified"];
+
+/// Filters on dates, a bare number like `2024` is a year for them instead of bytes
+const DATES: &[&str] = &["modified"];
+
+/// Recursive descent over the tokens from `lexer::tokenize`.
+///
+/// ```text
+/// or      := and (("OR" | "|") and)*
+/// and     := unary ("AND"? unary)*
+/// unary   := ("NOT" | "-") unary | primary
+/// primary := "(" or ")" | term
+/// term    := ("case:" | "nocase:" | "smartcase:")? name ":" args
+/// ```
+///
+/// Terms that don't make sense, like an unknown function, are left out of the tree and
+/// reported, the rest of the query still works. Syntax errors fail the whole query
+pub struct Parser<'a> {
+    tokens: Vec<Token>,
+    options: &'a ParseOptions,
+    pos: usize,
+    errors: Vec<ParseError>,
+
+    // Errors at the end of the query point here
+    len: usize,
+}
+
+impl Parser<'_> {
+    /// `None` if nothing usable was left, an empty query matches everything
+    pub fn parse(q: &str, options: &ParseOptions) -> (Option<Expr>, Vec<ParseError>) {
+        let tokens = match tokenize(q) {
+            Ok(tokens) => tokens,
+            Err(e) => return (None, vec![e]),
+        };
+
+        let mut parser = Parser { tokens, options, pos: 0, errors: Vec::new(), len: q.len() };
+
+        let expr = match parser.or() {
+            Ok(expr) => match parser.next() {
+                None => expr,
+                Some(Token { kind: TokenKind::RParen, span }) => {
+                    return (None, vec![ParseError::new("Unmatched closing parenthesis", span)]);
+                }
+                Some(_) => unreachable!("`and` takes every token up to a `)`"),
+            },
+            Err(e) => return (None, vec![e]),
+        };
+
+        (expr, parser.errors)
+    }
+
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Where the next token is, or the end of the query
+    fn here(&self) -> Span {
+        self.tokens
+            .get(self.pos)
+            .map(|t| t.span)
+            .unwrap_or(Span { start: self.len, end: self.len })
+    }
+
+    /// Fails on `operator` if nothing that can be matched follows it
+    fn expect_operand(&self, operator: &str, at: Span) -> Result<(), ParseError> {
+        if self.starts_operand() {
+            Ok(())
+        } else {
+            Err(ParseError::new(format!("Expected something after {}", operator), at)
+                .expected("a term, NOT or ("))
+        }
+    }
+
+    fn or(&mut self) -> Result<Option<Expr>, ParseError> {
+        if self.peek() == Some(&TokenKind::Or) {
+            return Err(ParseError::new("Expected something before OR", self.here())
+                .expected("a term, NOT or ("));
+        }
+
+        let mut any = Vec::new();
+        any.extend(self.and()?);
+
+        while self.peek() == Some(&TokenKind::Or) {
+            let at = self.here();
+            self.next();
+            self.expect_operand("OR", at)?;
+            any.extend(self.and()?);
+        }
+
+        Ok(combine(any, Expr::Or))
+    }
+
+    fn and(&mut self) -> Result<Option<Expr>, ParseError> {
+        let mut all = Vec::new();
+
+        loop {
+            match self.peek() {
+                Some(TokenKind::And) => {
+                    let at = self.here();
+                    self.next();
+                    self.expect_operand("AND", at)?;
+                }
+                _ if self.starts_operand() => {}
+                _ => break,
+            }
+
+            all.extend(self.unary()?);
+        }
+
+        Ok(combine(all, Expr::And))
+    }
+
+    fn starts_operand(&self) -> bool {
+        matches!(self.peek(), Some(TokenKind::Term(_) | TokenKind::LParen | TokenKind::Not))
+    }
+
+    fn unary(&mut self) -> Result<Option<Expr>, ParseError> {
+        if self.peek() == Some(&TokenKind::Not) {
+            let at = self.here();
+            self.next();
+            self.expect_operand("NOT", at)?;
+            return Ok(self.unary()?.map(|e| Expr::Not(Box::new(e))));
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Option<Expr>, ParseError> {
+        match self.next() {
+            Some(Token { kind: TokenKind::LParen, span: open }) => {
+                let inner = self.or()?;
+                match self.next().map(|t| t.kind) {
+                    Some(TokenKind::RParen) => Ok(inner),
+                    _ => Err(ParseError::new("Missing closing parenthesis", open).expected(")")),
+                }
+            }
+            Some(Token { kind: TokenKind::Term(text), span }) => match parse_term(&text, span, self.options) {
+                Ok(call) => Ok(Some(call)),
+                Err(e) => {
+                    self.errors.push(e);
+                    Ok(None)
+                }
+            },
+            _ => unreachable!("only called when `starts_operand`"),
+        }
+    }
+}
+
+/// Parts that were left out don't count, a single part needs no wrapper.
+/// Parts that open files go last, so they're only read if the others didn't decide
+fn combine(mut parts: Vec<Expr>, wrap: fn(Vec<Expr>) -> Expr) -> Option<Expr> {
+    parts.sort_by_key(Expr::reads_content);
+    match parts.len() {
+        0 => None,
+        1 => parts.pop(),
+        _ => Some(wrap(parts)),
+    }
+}
+
+/// `function:args`, split at the first `:` so values can hold more, maybe after
+/// a case modifier like `nocase:`. `span` is where `text` is in the query
+fn parse_term(mut text: &str, mut span: Span, options: &ParseOptions) -> Result<Expr, ParseError> {
+    let mut options = Cow::Borrowed(options);
+    for (modifier, case) in CaseMode::MODIFIERS {
+        if let Some(rest) = text.strip_prefix(modifier).and_then(|rest| rest.strip_prefix(':')) {
+            options.to_mut().case = case;
+            span.start += text.len() - rest.len();
+            text = rest;
+            break;
+        }
+    }
+    let options = options.as_ref();
+
+    let Some((function, raw_args)) = text.split_once(':') else {
+        return Err(ParseError::new(format!("'{}' isn't a filter", text), span)
+            .expected("name:value")
+            .suggest(Some(format!("has:{}", text))));
+    };
+
+    let name_span = Span { start: span.start, end: span.start + function.len() };
+    let Some(&func) = FUNCTION_MAP.get(function) else {
+        return Err(ParseError::new(format!("Unknown filter '{}'", function), name_span)
+            .suggest(did_you_mean(function, PREDICATES.iter().map(|p| p.name))));
+    };
+
+    let args_span = Span { start: name_span.end + 1, end: span.end };
+    let invalid = |e: String| ParseError::new(e, args_span);
+
+    if raw_args.trim().is_empty() {
+        return Err(ParseError::new(format!("Missing value for {}", function), Span { start: name_span.end, end: span.end })
+            .expected("a value after :"));
+    }
+
+    let mut args = parse_args(raw_args, function, options).map_err(invalid)?;
+    if matches!(function, "ext" | "type") {
+        args = extensions(function, args, options, args_span)?;
+    }
+
+    // Catches arguments of the wrong kind, like a date given to `size`
+    let probe = Candidate { path: "/", meta: None, lines: None };
+    if func(&probe, &args).is_err() {
+        args = exact(&args, function, options).map_err(invalid)?;
+    }
+    func(&probe, &args).map_err(invalid)?;
+
+    // Whatever text is left goes to a text filter, it's folded once here instead of for every file
+    let mut args = compile_text(args, options);
+    if function == "contains" {
+        args = content_search(args, options);
+    }
+
+    Ok(Expr::Call { func, args, ranks: RANKING.contains(&function) })
+}
+
+/// `ext:` and `type:` values as the extensions they stand for, `jpg,png` or `(jpg, png)` is either.
+/// Groups are looked up here, so changing them in the settings needs the query parsed again
+fn extensions(function: &str, args: Arg, options: &ParseOptions, span: Span) -> Result<Arg, ParseError> {
+    let values = match args {
+        Arg::Literal(text) => vec![text],
+        Arg::Group(group) => {
+            let mut values = Vec::new();
+            for arg in group {
+                match arg {
+                    Arg::Literal(text) => values.push(text),
+                    // Left for the filter to complain about
+                    _ => return Ok(arg),
+                }
+            }
+            values
+        }
+        _ => return Ok(args),
+    };
+
+    let mut extensions = Vec::new();
+    for value in values.iter().flat_map(|text| text.split(',')) {
+        let value = filetypes::normalize(value);
+        if value.is_empty() {
+            continue;
+        }
+        if function == "ext" {
+            extensions.push(value);
+            continue;
+        }
+
+        let Some(group) = filetypes::lookup(&value, &options.file_types) else {
+            return Err(ParseError::new(format!("Unknown file type '{}'", value), span)
+                .suggest(did_you_mean(&value, filetypes::names(&options.file_types))));
+        };
+        extensions.extend(group.into_iter().map(filetypes::normalize));
+    }
+
+    if extensions.is_empty() {
+        return Err(ParseError::new(format!("Missing value for {}", function), span).expected("an extension like rs"));
+    }
+
+    Ok(Arg::Extensions(extensions))
+}
+
+fn compile_text(args: Arg, options: &ParseOptions) -> Arg {
+    match args {
+        Arg::Literal(text) | Arg::Path(text) => {
+            let folding = options.folding(options.case.ignores_case(&text));
+            let needle = folding.apply(&text).into_owned();
+            Arg::Pattern(Pattern { kind: PatternKind::Text(needle), folding })
+        }
+        Arg::Group(group) => Arg::Group(group.into_iter().map(|arg| compile_text(arg, options)).collect()),
+        _ => args,
+    }
+}
+
+/// Text and regexes for `contains:` with the settings for reading files
+fn content_search(args: Arg, options: &ParseOptions) -> Arg {
+    match args {
+        Arg::Pattern(pattern) => Arg::Content(Box::new(ContentSearch {
+            pattern,
+            encoding: options.encoding,
+            read_limit: options.read_limit,
+        })),
+        Arg::Group(group) => Arg::Group(group.into_iter().map(|arg| content_search(arg, options)).collect()),
+        _ => args,
+    }
+}
+
+fn strip_quotes(s: &str) -> String {
+    if (s.starts_with('"') || s.starts_with('\'')) && s.len() >= 2 && s.chars().last() == s.chars().next() {
+        return s[1..s.len() - 1].to_string();
+    }
+    s.to_string()
+}
+
+fn split_args(input: &str) -> Result<Vec<String>, String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut in_quotes = false;
+    let mut quote_char = '\0';
+
+    for c in input.chars() {
+        match c {
+            // Handle quotes
+            '"' | '\'' => {
+                if in_quotes && c == quote_char {
+                    in_quotes = false;
+                } else if !in_quotes {
+                    in_quotes = true;
+                    quote_char = c;
+                }
+                current.push(c);
+            }
+
+            // Handle parentheses nesting
+            '(' if !in_quotes => {
+                depth += 1;
+                current.push(c);
+            }
+            ')' if !in_quotes => {
+                if depth == 0 {
+                    return Err("Unmatched closing parenthesis".into());
+                }
+                depth -= 1;
+                current.push(c);
+            }
+
+            // Handle commas at top level
+            ',' if !in_quotes && depth == 0 => {
+                result.push(current.trim().to_string());
+                current.clear();
+            }
+
+            // Default case
+            _ => {
+                current.push(c);
+            }
+        }
+    }
+
+    if in_quotes {
+        return Err("Unclosed quote in arguments".into());
+    }
+    if depth != 0 {
+        return Err("Unmatched opening parenthesis in arguments".into());
+    }
+
+    if !current.trim().is_empty() {
+        result.push(current.trim().to_string());
+    }
+
+    Ok(result)
+}
+
+/// A size, a date or an age, sizes first so a bare number is bytes. Filters in `DATES` try dates first
+fn parse_unit(s: &str, function: &str, options: &ParseOptions) -> Result<Unit, String> {
+    if DATES.contains(&function) {
+        if let Ok(date) = dates::parse_date(s, options.date_order, dates::now()) {
+            return Ok(date);
+        }
+    }
+
+    let size = match sizes::parse_size(s, options.size_units) {
+        Ok(size) => return Ok(Unit::Size(size)),
+        Err(e) => e,
+    };
+
+    match dates::parse_date(s, options.date_order, dates::now()) {
+        Ok(date) => Ok(date),
+        // Say what's wrong with whatever it looks like
+        Err(e) if s.contains(['/', '-', ':']) => Err(e),
+        Err(_) if s.starts_with(|c: char| c.is_ascii_digit()) => Err(size),
+        Err(_) => Err(format!("'{}' is neither a size nor a date", s)),
+    }
+}
+
+/// A bare value given to a filter that compares means `=`, so `size:0` is `size:=0`.
+/// Only used on filters that didn't take the value as text
+fn exact(args: &Arg, function: &str, options: &ParseOptions) -> Result<Arg, String> {
+    match args {
+        Arg::Literal(text) => parse_unit(text, function, options).map(|value| Arg::Conditional { operator: Operator::Eq, value }),
+        Arg::Group(group) => group.iter().map(|arg| exact(arg, function, options)).collect::<Result<_, _>>().map(Arg::Group),
+        _ => Ok(args.clone()),
+    }
+}
+
+/// `Some` if `raw` is a regex, see `lexer::split_regex`. With `re:` in front it has to be one.
+/// The `i` flag ignores case whatever the case mode
+fn parse_regex(raw: &str, options: &ParseOptions) -> Option<Result<Pattern, String>> {
+    let (pattern, flags) = match raw.strip_prefix("re:") {
+        Some(rest) => match split_regex(rest, true) {
+            Some(parts) => parts,
+            None => return Some(Err(format!("'{}' isn't a /pattern/flags regex", rest))),
+        },
+        None => split_regex(raw, false)?,
+    };
+
+    // The regex engine takes care of case
+    let folding = options.folding(false);
+    let mut builder = regex::RegexBuilder::new(&folding.apply(pattern));
+    builder.case_insensitive(options.case.ignores_case(pattern));
+    for flag in flags.chars() {
+        match flag {
+            'i' => builder.case_insensitive(true),
+            'm' => builder.multi_line(true),
+            's' => builder.dot_matches_new_line(true),
+            'x' => builder.ignore_whitespace(true),
+            _ => builder.swap_greed(true),
+        };
+    }
+
+    // The last line of regex's own error says what's wrong, the rest draws the pattern
+    Some(builder.build().map(|regex| Pattern { kind: PatternKind::Regex(regex), folding }).map_err(|e| {
+        let e = e.to_string();
+        format!("Invalid regex: {}", e.lines().last().unwrap_or_default().trim_start_matches("error: "))
+    }))
+}
+
+/// Like in .gitignore, a glob without a `/` matches names and one with folders
+/// can start at any depth, unless it starts with `/`
+fn parse_glob(raw: &str, options: &ParseOptions) -> Result<Pattern, String> {
+    let raw = raw.strip_prefix("./").unwrap_or(raw);
+    let names_only = !raw.contains('/');
+
+    let glob = if names_only || raw.starts_with('/') || raw.starts_with("**") {
+        raw.to_string()
+    } else {
+        format!("**/{}", raw)
+    };
+
+    // Globs take care of case too
+    let folding = options.folding(false);
+    GlobBuilder::new(&folding.apply(&glob))
+        .literal_separator(true)
+        .case_insensitive(options.case.ignores_case(&glob))
+        .build()
+        .map(|glob| Pattern { kind: PatternKind::Glob { matcher: glob.compile_matcher(), names_only }, folding })
+        .map_err(|e| format!("Invalid glob: {}", e))
+}
+
+/// Arg Parsing
+/// Literal
+///     Any text sorounded by quotes or numbers
+///     Ex. "some text", 123, 45.67
+///
+/// Path
+///     A path, starts with "./" or "/". Using the " it's optional.
+///     Ex. /some-path, ./../folder, "/a/b/c"
+///
+/// Pattern
+///     A /regex/flags, or unquoted text with *, ? or [ as a glob. A regex needs some regex
+///     syntax, or re: in front, a / in it is written \/
+///     Ex. /^v\d+$/i, re:/home/, /^\/docs\//, *.rs, **/tests/*.py
+///
+/// Conditional
+///     Starts with a `Operator` and should be followed by a `Unit`
+///     Ex. >100Mb, <=6/12/2020, !=0, =2024-01, <7d, >=today
+///
+/// Range
+///     Two sizes, or two dates or ages, separated by "..", both ends included. Only for size: and modified:
+///     Ex. 10kb..2mb, 2024-01..2024-03, 2w..1w
+///
+/// Group
+///     Starts with "(", represents multiple Arguments separated by ","
+///     Ex. ("abc", >100Ms)
+///
+fn parse_args(raw: &str, function: &str, options: &ParseOptions) -> Result<Arg, String> {
+    let raw = raw.trim();
+
+    if raw.is_empty() {
+        return Err("Missing value after ':'".into());
+    }
+
+    let first_char = raw.chars().next().unwrap();
+
+    // Group
+    if first_char == '(' && raw.ends_with(')') {
+        let inner = &raw[1..raw.len() - 1];
+        let args = split_args(inner)?;
+        let parsed: Result<Vec<Arg>, String> = args
+            .iter()
+            .map(|s| parse_args(s, function, options))
+            .collect();
+        return Ok(Arg::Group(parsed?));
+    }
+
+    // Conditional
+    // (< | <= | = | != | >= | >){num}{unit}
+    //                           |   mm/dd/yyyy
+    let operators = [
+        ("<=", Operator::Le),
+        (">=", Operator::Ge),
+        ("!=", Operator::Ne),
+        ("<", Operator::Lt),
+        (">", Operator::Gt),
+        ("=", Operator::Eq),
+    ];
+    if let Some((symbol, op)) = operators.into_iter().find(|(symbol, _)| raw.starts_with(symbol)) {
+        let raw_value = raw[symbol.len()..].trim(); // strip the operator
+
+        return parse_unit(raw_value, function, options)
+            .map(|value| Arg::Conditional { operator: op, value })
+            .map_err(|e| format!("Could not parse conditional value: {}", e));
+    }
+
+    // Range
+    // {size}..{size} | {date or age}..{date or age}, only for filters in `RANGES`
+    if let Some((from, to)) = raw.split_once("..").filter(|_| RANGES.contains(&function)) {
+        match (parse_unit(from.trim(), function, options), parse_unit(to.trim(), function, options)) {
+            (Err(_), Err(_)) => {}
+            (Err(e), _) | (_, Err(e)) => return Err(format!("Invalid range: {}", e)),
+            (Ok(from), Ok(to)) => {
+                let backwards = match (&from, &to) {
+                    (Unit::Size(from), Unit::Size(to)) => from > to,
+                    (Unit::Date { first, .. }, Unit::Date { last, .. }) => first > last,
+                    (Unit::Size(_), _) | (_, Unit::Size(_)) => {
+                        return Err("A range goes from a size to a size or from a date to a date".into());
+                    }
+                    // Ages can go either way, `2d..7d` reads as well as `7d..2d`
+                    _ => false,
+                };
+                if backwards {
+                    return Err(format!("Range '{}' ends before it starts", raw));
+                }
+
+                return Ok(Arg::Range { from, to });
+            }
+        }
+    }
+
+    // Regex
+    // /pattern/flags when the pattern has regex syntax, or re:/pattern/flags for any pattern
+    if let Some(regex) = parse_regex(raw, options) {
+        return regex.map(Arg::Pattern);
+    }
+
+    // Glob
+    // Anything unquoted with *, ? or [ in it
+    if !raw.starts_with(['"', '\'']) && raw.contains(['*', '?', '[']) {
+        return parse_glob(raw, options).map(Arg::Pattern);
+    }
+
+    // Path - must start with / or ./
+    if
+        raw.starts_with('/') ||
+        raw.starts_with("./") ||
+        raw.starts_with("\"/") ||
+        raw.starts_with("\"./")
+    {
+        return Ok(Arg::Path(strip_quotes(raw)));
+    }
+
+    // Literal - quoted string
+    if first_char == '"' || first_char == '\'' {
+        if raw.len() < 2 || !raw.ends_with(first_char) {
+            return Err("Unclosed string".to_string());
+        }
+
+        return Ok(Arg::Literal(strip_quotes(raw)));
+    }
+
+    // Literal - number
+    if raw.parse::<i32>().is_ok() || raw.parse::<f64>().is_ok() {
+        return Ok(Arg::Literal(raw.to_string()));
+    }
+
+    // Fallback: treat as literal
+    Ok(Arg::Literal(raw.to_string()))
+}
@@ -0,0 +1,75 @@
+use serde::{ Deserialize, Serialize };
+
+/// What `kb`, `mb` and the like mean, `kib` and friends are always powers of 1024
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeUnits {
+    /// 1 kb is 1000 bytes
+    #[default]
+    Decimal,
+
+    /// 1 kb is 1024 bytes, like most file managers on Windows
+    Binary,
+}
+
+impl SizeUnits {
+    fn base(self) -> u64 {
+        match self {
+            SizeUnits::Decimal => 1000,
+            SizeUnits::Binary => 1024,
+        }
+    }
+}
+
+const DECIMAL: [&str; 6] = ["b", "kb", "mb", "gb", "tb", "pb"];
+const BINARY: [&str; 6] = ["b", "kib", "mib", "gib", "tib", "pib"];
+
+/// Converts a size like `1.5gb`, `10 KiB` or `200` (bytes) to bytes
+pub fn parse_size(s: &str, units: SizeUnits) -> Result<u64, String> {
+    let s = s.trim().to_lowercase();
+
+    let idx = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (num_part, suffix) = s.split_at(idx);
+
+    let suffix = match suffix.trim() {
+        "" => "b",
+        suffix => suffix,
+    };
+    let multiplier = if let Some(power) = DECIMAL.iter().position(|&u| u == suffix) {
+        units.base().pow(power as u32)
+    } else if let Some(power) = BINARY.iter().position(|&u| u == suffix) {
+        1024u64.pow(power as u32)
+    } else {
+        return Err(format!("Invalid size suffix '{}', expected b, kb to pb or kib to pib", suffix));
+    };
+
+    let num: f64 = num_part
+        .parse()
+        .map_err(|_| format!("Invalid size number '{}'", num_part))?;
+
+    let bytes = (num * multiplier as f64).round();
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("Size '{}' is too big", s));
+    }
+
+    Ok(bytes as u64)
+}
+
+/// `1.5 MB` style, in the same units `parse_size` reads so it can be typed back in.
+/// Binary sizes are written `MiB` so they mean the same whatever the setting
+pub fn format_size(bytes: u64, units: SizeUnits) -> String {
+    let names = match units {
+        SizeUnits::Decimal => ["B", "KB", "MB", "GB", "TB", "PB"],
+        SizeUnits::Binary => ["B", "KiB", "MiB", "GiB", "TiB", "PiB"],
+    };
+    let base = units.base() as f64;
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= base && unit < names.len() - 1 {
+        size /= base;
+        unit += 1;
+    }
+
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", size, names[unit]) }
+}
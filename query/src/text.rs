@@ -0,0 +1,97 @@
+use std::borrow::Cow;
+
+use serde::{ Deserialize, Serialize };
+use unicode_normalization::{ char::is_combining_mark, UnicodeNormalization };
+
+/// Whether text filters care about case, per query in `ParseOptions` or per term
+/// with `case:`, `nocase:` and `smartcase:` in front of it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseMode {
+    Sensitive,
+    Insensitive,
+
+    /// Like ripgrep, case only matters if what's typed has an uppercase letter
+    #[default]
+    Smart,
+}
+
+impl CaseMode {
+    /// The term modifier for each mode, like `nocase:has:readme`
+    pub const MODIFIERS: [(&'static str, CaseMode); 3] = [
+        ("case", CaseMode::Sensitive),
+        ("nocase", CaseMode::Insensitive),
+        ("smartcase", CaseMode::Smart),
+    ];
+
+    /// Whether `typed` is matched ignoring case. Escapes like `\D` in a regex
+    /// don't count as uppercase
+    pub fn ignores_case(self, typed: &str) -> bool {
+        match self {
+            CaseMode::Sensitive => false,
+            CaseMode::Insensitive => true,
+            CaseMode::Smart => {
+                let mut escaped = false;
+                !typed.chars().any(|c| {
+                    let upper = !escaped && c.is_uppercase();
+                    escaped = !escaped && c == '\\';
+                    upper
+                })
+            }
+        }
+    }
+}
+
+/// Unicode has several ways to write the same letter, `é` can be one char or an `e`
+/// and a combining accent. Both sides are normalized the same way before comparing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization {
+    /// Compared as they are
+    None,
+
+    /// Accents are combined into their letters
+    #[default]
+    Nfc,
+
+    /// Like `Nfc`, and look-alikes such as `ﬁ` or `①` become `fi` and `1`
+    Nfkc,
+}
+
+/// How a path is prepared before a text filter looks at it, the typed text goes
+/// through the same once when the query is parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Folding {
+    pub lowercase: bool,
+    pub normalization: Normalization,
+
+    /// `résumé` matches `resume`
+    pub diacritics: bool,
+}
+
+impl Folding {
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        // Nothing to normalize or fold in most file names
+        if text.is_ascii() {
+            return match self.lowercase && text.bytes().any(|b| b.is_ascii_uppercase()) {
+                true => Cow::Owned(text.to_ascii_lowercase()),
+                false => Cow::Borrowed(text),
+            };
+        }
+
+        let mut text = match self.normalization {
+            Normalization::None => Cow::Borrowed(text),
+            Normalization::Nfc => Cow::Owned(text.nfc().collect()),
+            Normalization::Nfkc => Cow::Owned(text.nfkc().collect()),
+        };
+
+        if self.diacritics {
+            text = Cow::Owned(text.nfd().filter(|&c| !is_combining_mark(c)).nfc().collect());
+        }
+        if self.lowercase {
+            text = Cow::Owned(text.to_lowercase());
+        }
+
+        text
+    }
+}
@@ -0,0 +1,257 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+use chrono::{ NaiveDateTime, TimeDelta };
+
+use crate::content::{ ContentSearch, LineSink };
+use crate::text::Folding;
+use fs_tree_db::FileMeta;
+
+/// How well a file matched a filter, `None` if it didn't. Filters that only pass
+/// or fail score 0, `fuzzy` is one that ranks
+pub type Score = Option<u32>;
+
+pub type FunctionTy = fn(&Candidate, &Arg) -> Result<Score, String>;
+
+/// A file being matched against a query.
+/// Files coming from the index carry their metadata, so filters don't have to stat them
+#[derive(Clone, Copy)]
+pub struct Candidate<'a> {
+    pub path: &'a str,
+    pub meta: Option<&'a FileMeta>,
+
+    /// Collects the lines `contains:` matched, when they're wanted
+    pub lines: Option<&'a LineSink>,
+}
+
+impl Candidate<'_> {
+    /// Cached metadata if there's any, otherwise asks the disk
+    pub fn meta(&self) -> Option<Cow<'_, FileMeta>> {
+        match self.meta {
+            Some(meta) => Some(Cow::Borrowed(meta)),
+            None => FileMeta::read(Path::new(self.path)).map(Cow::Owned),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Ge,
+    Gt,
+}
+
+impl Operator {
+    /// Compares `value` to `first..=last`, every moment of a date like `2024-01`.
+    /// Sizes are a single value, with `first == last`
+    pub fn holds<T: PartialOrd>(self, value: T, first: T, last: T) -> bool {
+        match self {
+            Operator::Lt => value < first,
+            Operator::Le => value <= last,
+            Operator::Eq => first <= value && value <= last,
+            Operator::Ne => value < first || last < value,
+            Operator::Ge => value >= first,
+            Operator::Gt => value > last,
+        }
+    }
+
+    /// The same comparison with the sides swapped, `<` becomes `>`
+    pub fn reversed(self) -> Self {
+        match self {
+            Operator::Lt => Operator::Gt,
+            Operator::Le => Operator::Ge,
+            Operator::Ge => Operator::Le,
+            Operator::Gt => Operator::Lt,
+            same => same,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Unit {
+    /// Every moment from `first` to `last` in local time, see `dates::parse_date`
+    Date { first: NaiveDateTime, last: NaiveDateTime },
+
+    /// How long ago, like `7d`. `<7d` is newer than a week
+    Age(TimeDelta),
+
+    Size(u64)
+}
+
+/// Text matching that's compiled when the query is parsed
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub kind: PatternKind,
+
+    /// What's done to a path before it's matched, the pattern already went through it
+    pub folding: Folding,
+}
+
+#[derive(Debug, Clone)]
+pub enum PatternKind {
+    /// Found anywhere in the text
+    Text(String),
+
+    /// `/regex/flags`, found anywhere in the text
+    Regex(regex::Regex),
+
+    /// `*.rs` or `**/tests/*.py`, `*` stays within a folder and `**` crosses them
+    Glob {
+        matcher: globset::GlobMatcher,
+
+        /// Without a `/` a glob is matched against names, like in .gitignore
+        names_only: bool,
+    },
+}
+
+impl Pattern {
+    pub fn is_match(&self, text: &str) -> bool {
+        let text = self.folding.apply(text);
+
+        match &self.kind {
+            PatternKind::Text(needle) => text.contains(needle.as_str()),
+            PatternKind::Regex(regex) => regex.is_match(&text),
+            PatternKind::Glob { matcher, .. } => matcher.is_match(text.as_ref()),
+        }
+    }
+
+    /// Globs without a `/`
+    pub fn names_only(&self) -> bool {
+        matches!(self.kind, PatternKind::Glob { names_only: true, .. })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Arg {
+    Literal(String),  // Strings or numbers
+
+    Path(String), // Starts with / or ./, matched as text by the filters that take text
+
+    // Represent a condion such as modified:>2024-01-01
+    // In this example: operator=">", value=Unit::Date("2024-01-01")
+    Conditional {
+        operator: Operator,
+        value: Unit,
+    },
+
+    Pattern(Pattern),
+
+    // Both ends included, like size:10kb..2mb or modified:2024-01..2024-03
+    Range {
+        from: Unit,
+        to: Unit,
+    },
+
+    Group(Vec<Arg>),
+
+    // For ext: and type:, lowercase and without the dot. Any of them matches
+    Extensions(Vec<String>),
+
+    // For contains:, text or a regex and how files are read
+    Content(Box<ContentSearch>),
+}
+
+impl Arg {
+    /// Whether files have to be opened to check it
+    pub fn reads_content(&self) -> bool {
+        match self {
+            Arg::Content(_) => true,
+            Arg::Group(group) => group.iter().any(Arg::reads_content),
+            _ => false,
+        }
+    }
+}
+
+/// A parsed query
+#[derive(Debug, Clone)]
+pub enum Expr {
+    /// A sucessful parsed `function:args` item
+    Call {
+        func: FunctionTy, // Function to call,
+        args: Arg,
+
+        /// Scores vary between matches, so results can be ranked
+        ranks: bool,
+    },
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    /// Calls that fail, like a metadata filter on a file that's gone, don't match
+    pub fn matches(&self, candidate: &Candidate) -> bool {
+        match self {
+            Expr::Call { func, args, .. } => matches!(func(candidate, args), Ok(Some(_))),
+            // Lines found in a file that's ruled out for having them aren't a reason it matched
+            Expr::Not(inner) => !inner.matches(&Candidate { lines: None, ..*candidate }),
+            Expr::And(all) => all.iter().all(|e| e.matches(candidate)),
+            Expr::Or(any) => any.iter().any(|e| e.matches(candidate)),
+        }
+    }
+
+    /// Like `matches`, with scores added up for AND and the best one for OR.
+    /// A NOT that matches scores 0
+    pub fn score(&self, candidate: &Candidate) -> Score {
+        match self {
+            Expr::Call { func, args, .. } => func(candidate, args).unwrap_or(None),
+            Expr::Not(inner) => if inner.matches(&Candidate { lines: None, ..*candidate }) { None } else { Some(0) },
+            Expr::And(all) => all.iter().try_fold(0, |sum, e| Some(sum + e.score(candidate)?)),
+            Expr::Or(any) => any.iter().filter_map(|e| e.score(candidate)).max(),
+        }
+    }
+
+    /// Whether some part of it opens files, see `prefilter`
+    pub fn reads_content(&self) -> bool {
+        match self {
+            Expr::Call { args, .. } => args.reads_content(),
+            Expr::Not(inner) => inner.reads_content(),
+            Expr::And(all) | Expr::Or(all) => all.iter().any(Expr::reads_content),
+        }
+    }
+
+    /// Like `matches` with only the path and metadata filters, `None` when it depends
+    /// on what's in the file. Files it rules out are never opened
+    pub fn prefilter(&self, candidate: &Candidate) -> Option<bool> {
+        match self {
+            Expr::Call { args, .. } if args.reads_content() => None,
+            Expr::Call { .. } => Some(self.matches(candidate)),
+            Expr::Not(inner) => inner.prefilter(candidate).map(|matched| !matched),
+            Expr::And(all) => {
+                let mut known = Some(true);
+                for e in all {
+                    match e.prefilter(candidate) {
+                        Some(false) => return Some(false),
+                        Some(true) => {}
+                        None => known = None,
+                    }
+                }
+                known
+            }
+            Expr::Or(any) => {
+                let mut known = Some(false);
+                for e in any {
+                    match e.prefilter(candidate) {
+                        Some(true) => return Some(true),
+                        Some(false) => {}
+                        None => known = None,
+                    }
+                }
+                known
+            }
+        }
+    }
+
+    /// Whether some part of it gives scores worth ranking by
+    pub fn ranks(&self) -> bool {
+        match self {
+            Expr::Call { ranks, .. } => *ranks,
+            // Not matching has no degrees
+            Expr::Not(_) => false,
+            Expr::And(all) | Expr::Or(all) => all.iter().any(Expr::ranks),
+        }
+    }
+}
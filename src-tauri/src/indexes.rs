@@ -0,0 +1,345 @@
+use std::collections::{ BTreeMap, HashMap };
+use std::path::{ Path, PathBuf };
+use std::sync::{ Arc, Mutex, RwLock };
+
+use fs_tree_db::format;
+use fs_tree_db::registry::{ self, IndexConfig };
+use fs_tree_db::{ CancelToken, CrawlOptions, ExcludeProfile, Progress, SharedTree, Tree, TreeBuilder, TreeWatcher };
+use serde::Serialize;
+use tauri::{ AppHandle, Emitter };
+use tokio::sync::broadcast::error::RecvError;
+
+/// An index as the UI lists it
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexInfo {
+    #[serde(flatten)]
+    pub config: IndexConfig,
+    pub loaded: bool,
+    pub building: bool,
+
+    /// Number of files, if loaded
+    pub files: Option<usize>,
+}
+
+/// Sent as `index-progress`
+#[derive(Clone, Serialize)]
+struct IndexProgress {
+    index: String,
+    #[serde(flatten)]
+    progress: Progress,
+}
+
+/// An index in memory, dropping it stops its watcher
+struct Loaded {
+    tree: SharedTree,
+    _watcher: TreeWatcher,
+}
+
+/// Every index the app knows about, and the ones currently loaded.
+/// Indexes load, unload and rebuild independently of each other
+pub struct IndexManager {
+    registry: PathBuf,
+    configs: RwLock<Vec<IndexConfig>>,
+    loaded: RwLock<BTreeMap<String, Loaded>>,
+
+    // Builds in progress, so they can be cancelled
+    building: Mutex<HashMap<String, CancelToken>>,
+}
+
+impl IndexManager {
+    /// Reads the registry at `registry`, starting out empty if there's none yet
+    pub fn open(registry: PathBuf) -> Self {
+        let configs = registry::read(&registry);
+
+        IndexManager {
+            registry,
+            configs: RwLock::new(configs),
+            loaded: RwLock::new(BTreeMap::new()),
+            building: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Where a new index called `name` gets saved, next to the registry
+    pub fn save_path_for(&self, name: &str) -> PathBuf {
+        registry::save_path_for(&self.registry, name)
+    }
+
+    /// Nothing has been set up yet, the registry gets written once the first-run setup is done
+    pub fn is_new(&self) -> bool {
+        !self.registry.exists()
+    }
+
+    /// Writes the registry, even an empty one, so the first-run setup isn't shown again
+    pub fn save_registry(&self) -> Result<(), String> {
+        registry::write(&self.registry, &self.configs.read().unwrap())
+            .map_err(|e| format!("Couldn't save index list: {}", e))
+    }
+
+    pub fn config(&self, name: &str) -> Option<IndexConfig> {
+        self.configs.read().unwrap().iter().find(|c| c.name == name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.configs.read().unwrap().iter().map(|c| c.name.clone()).collect()
+    }
+
+    pub fn list(&self) -> Vec<IndexInfo> {
+        let loaded = self.loaded.read().unwrap();
+        let building = self.building.lock().unwrap();
+
+        self.configs
+            .read()
+            .unwrap()
+            .iter()
+            .map(|config| {
+                let tree = loaded.get(&config.name);
+                IndexInfo {
+                    config: config.clone(),
+                    loaded: tree.is_some(),
+                    building: building.contains_key(&config.name),
+                    files: tree.map(|l| l.tree.read().unwrap().len()),
+                }
+            })
+            .collect()
+    }
+
+    /// Registers a new index, it's built the first time it gets loaded
+    pub fn add(&self, config: IndexConfig) -> Result<(), String> {
+        if config.name.trim().is_empty() {
+            return Err("An index needs a name".to_string());
+        }
+        if config.roots.is_empty() {
+            return Err("An index needs at least one folder".to_string());
+        }
+        if self.config(&config.name).is_some() {
+            return Err(format!("There's already an index called {}", config.name));
+        }
+
+        // Otherwise building one would overwrite the other's tree, and removing one delete it
+        let sharing = self.configs
+            .read()
+            .unwrap()
+            .iter()
+            .find(|c| same_file(&c.save_path, &config.save_path))
+            .map(|c| c.name.clone());
+        if let Some(other) = sharing {
+            return Err(format!("{} would be saved in the same file as {}, pick another name", config.name, other));
+        }
+
+        self.configs.write().unwrap().push(config);
+        self.save_registry()
+    }
+
+    /// Unloads the index and deletes its saved tree
+    pub fn remove(&self, name: &str) -> Result<(), String> {
+        self.cancel(name);
+        self.unload(name);
+
+        let removed = {
+            let mut configs = self.configs.write().unwrap();
+            let i = configs.iter().position(|c| c.name == name).ok_or_else(|| not_found(name))?;
+            configs.remove(i)
+        };
+
+        let _ = std::fs::remove_file(&removed.save_path);
+        self.save_registry()
+    }
+
+    /// Loads the saved tree, building it if it's missing, unreadable or was saved for other roots
+    pub async fn load(&self, app: &AppHandle, name: &str, options: CrawlOptions) -> Result<(), String> {
+        let config = self.config(name).ok_or_else(|| not_found(name))?;
+        let save_path = &config.save_path;
+
+        let saved = if save_path.exists() {
+            match Tree::load(save_path) {
+                Ok(t) if t.roots() == config.roots => Some(t),
+                Ok(_) => None,
+                Err(e) => {
+                    eprintln!("Rebuilding {}: {}", name, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let tree = match saved {
+            Some(t) => {
+                // Loaded from an old format, save it again in the current one
+                if Tree::format_version(save_path).map_or(true, |v| v < format::FORMAT_VERSION) {
+                    t.save(save_path).map_err(|e| format!("Couldn't save tree: {}", e))?;
+                }
+                self.set_profile(name, t.profile().clone())?;
+                t
+            }
+            None => {
+                // Keep the profile of the tree being replaced if its header is still readable
+                let profile = Tree::read_header(save_path)
+                    .map(|header| header.exclude)
+                    .unwrap_or(config.profile);
+
+                self.build(app, name, profile, options).await?
+            }
+        };
+
+        self.install(app, name, tree).await
+    }
+
+    /// Builds the index from scratch. Without a `profile` the current one is reused
+    pub async fn rebuild(
+        &self,
+        app: &AppHandle,
+        name: &str,
+        options: CrawlOptions,
+        profile: Option<ExcludeProfile>
+    ) -> Result<(), String> {
+        let config = self.config(name).ok_or_else(|| not_found(name))?;
+
+        let tree = self.build(app, name, profile.unwrap_or(config.profile), options).await?;
+        self.install(app, name, tree).await
+    }
+
+    /// Frees the tree, it stays registered
+    pub fn unload(&self, name: &str) -> bool {
+        self.loaded.write().unwrap().remove(name).is_some()
+    }
+
+    pub fn cancel(&self, name: &str) -> bool {
+        match self.building.lock().unwrap().get(name) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The loaded indexes in `targets`, or every loaded one. Nothing stays locked,
+    /// so they can be searched while they load, unload or get updated
+    pub fn trees(&self, targets: Option<&[String]>) -> Vec<SharedTree> {
+        self.loaded
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(name, _)| targets.is_none_or(|t| t.contains(name)))
+            .map(|(_, index)| index.tree.clone())
+            .collect()
+    }
+
+    /// The indexes in `targets`, or all of them, that aren't loaded yet
+    pub fn unloaded(&self, targets: Option<&[String]>) -> Vec<IndexConfig> {
+        let loaded = self.loaded.read().unwrap();
+
+        self.configs
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|c| targets.is_none_or(|t| t.contains(&c.name)))
+            .filter(|c| !loaded.contains_key(&c.name))
+            .cloned()
+            .collect()
+    }
+
+    fn set_profile(&self, name: &str, profile: ExcludeProfile) -> Result<(), String> {
+        let changed = {
+            let mut configs = self.configs.write().unwrap();
+            match configs.iter_mut().find(|c| c.name == name) {
+                Some(config) if config.profile != profile => {
+                    config.profile = profile;
+                    true
+                }
+                _ => false,
+            }
+        };
+
+        if changed { self.save_registry() } else { Ok(()) }
+    }
+
+    /// Crawls and saves the index, forwarding progress to the UI as `index-progress` events
+    async fn build(
+        &self,
+        app: &AppHandle,
+        name: &str,
+        profile: ExcludeProfile,
+        options: CrawlOptions
+    ) -> Result<Tree, String> {
+        let config = self.config(name).ok_or_else(|| not_found(name))?;
+
+        let builder = TreeBuilder::with_roots(config.roots).profile(profile.clone()).options(options);
+        self.building.lock().unwrap().insert(name.to_string(), builder.cancel_token());
+
+        let mut progress = builder.progress();
+        let progress_app = app.clone();
+        let index = name.to_string();
+
+        // Ends once the builder is dropped, after the last snapshot has been sent
+        tauri::async_runtime::spawn(async move {
+            while progress.changed().await.is_ok() {
+                let progress = progress.borrow_and_update().clone();
+                progress_app.emit("index-progress", IndexProgress { index: index.clone(), progress }).unwrap();
+            }
+        });
+
+        let built = builder.build().await;
+        self.building.lock().unwrap().remove(name);
+        let tree = built.map_err(|e| e.to_string())?;
+
+        tree.save(&config.save_path).map_err(|e| format!("Couldn't save tree: {}", e))?;
+        self.set_profile(name, profile)?;
+
+        // Let the user know which folders couldn't be indexed
+        if !tree.crawl_errors().is_empty() {
+            app.emit("index-errors", tree.crawl_errors()).unwrap();
+        }
+
+        Ok(tree)
+    }
+
+    /// Makes `tree` the one queries against `name` run on, and starts watching it
+    async fn install(&self, app: &AppHandle, name: &str, tree: Tree) -> Result<(), String> {
+        let rules = tree.profile().rules.clone();
+        let shared: SharedTree = Arc::new(RwLock::new(tree));
+
+        // Keep the tree in sync with the disk and let the UI know what changed.
+        // Watching walks every folder, that takes seconds on a big index
+        let watched = shared.clone();
+        let watcher = tauri::async_runtime::spawn_blocking(move || TreeWatcher::new(watched, rules))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?;
+        let mut changes = watcher.subscribe();
+        let app = app.clone();
+
+        tauri::async_runtime::spawn(async move {
+            loop {
+                match changes.recv().await {
+                    Ok(change) => {
+                        app.emit("tree-changed", change).unwrap();
+                    }
+                    // Missed some changes, the UI should re-run the query
+                    Err(RecvError::Lagged(_)) => {
+                        app.emit("tree-stale", true).unwrap();
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        // Replacing an index stops the old watcher
+        self.loaded
+            .write()
+            .unwrap()
+            .insert(name.to_string(), Loaded { tree: shared, _watcher: watcher });
+
+        Ok(())
+    }
+}
+
+/// Compared ignoring case, some file systems do
+fn same_file(a: &Path, b: &Path) -> bool {
+    a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+fn not_found(name: &str) -> String {
+    format!("No index called {}", name)
+}
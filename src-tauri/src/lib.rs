@@ -1,31 +1,27 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::fs;
 use std::path::{ Path, PathBuf };
-use fs_tree_db::excludes::get_excludes;
-use fs_tree_db::Tree;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use fs_tree_db::excludes::{ ExcludeProfile, Walk };
+use fs_tree_db::{ locations, CrawlOptions, FileKind, FileMeta, IndexConfig, SuggestedRoot };
+use indexes::{ IndexInfo, IndexManager };
 use tauri::{ AppHandle, Emitter };
-use types::Node;
-use parser::Parser;
+use query::{ format_size, LineMatch, ParseError, ParseOptions, Query, SizeUnits, Span };
 use once_cell::sync::Lazy;
-use walkdir::WalkDir;
-use std::sync::RwLock;
-use rayon::prelude::*;
-use crossbeam::channel::unbounded;
+use serde::Serialize;
+use crossbeam::channel::{ unbounded, Sender };
 
-mod parser;
-mod macros;
-mod functions;
-mod types;
+mod indexes;
 
-// const DEFUALT_INITIAL_PATHL: &str = r#"C:\Users\Hyvnt\T"#;
-const DEFUALT_INITIAL_PATHL: &str = r#"C:\"#;
-const DEFAULT_SAVE_PATH: &str =
-    r#"C:\Users\Hyvnt\T\Rust\file-explorer\fs_tree_db\save\tree.bincode"#;
+/// Every index the app knows about, listed in the data dir next to their saved trees
+pub static INDEXES: Lazy<IndexManager> = Lazy::new(|| IndexManager::open(locations::index_registry()));
 
-pub static GLOBAL_TREE: Lazy<RwLock<Option<Tree>>> = Lazy::new(|| { RwLock::new(None) });
+/// Bumped by every `stream_query`, a search stops once a newer one started
+static QUERY_GENERATION: AtomicU64 = AtomicU64::new(0);
 
-pub fn with_tree<F, R>(f: F) -> R where F: FnOnce(&Tree) -> R {
-    let tree = GLOBAL_TREE.read().unwrap();
-    f(tree.as_ref().expect("Tree not initialized"))
+fn is_latest(generation: u64) -> bool {
+    QUERY_GENERATION.load(Ordering::Relaxed) == generation
 }
 
 #[tauri::command]
@@ -35,7 +31,8 @@ fn read_dir(
     limit: u32,
     show_full_path: bool
 ) -> Result<Vec<String>, String> {
-    let parent_path: String = initial_path.unwrap_or_else(|| DEFUALT_INITIAL_PATHL.to_string());
+    let parent_path: String = initial_path
+        .unwrap_or_else(|| locations::home_dir().to_string_lossy().to_string());
     dbg!(&parent_path, &path);
 
     let clean_path = path.trim_start_matches(&['/', '\\'][..]);
@@ -71,93 +68,344 @@ fn read_dir(
     }
 }
 
+/// Loads every registered index, one after the other.
+/// `options` come from the user's settings, they only matter if an index has to be built
 #[tauri::command]
-async fn load_tree() -> Result<(), String> {
-    let save_path = PathBuf::from(DEFAULT_SAVE_PATH);
+async fn load_indexes(app: AppHandle, options: Option<CrawlOptions>) -> Result<(), String> {
+    let options = options.unwrap_or_default();
+    let mut failed = Vec::new();
 
-    let new_tree = if save_path.exists() {
-        Tree::load(&save_path).expect("Couldn't load tree")
-    } else {
-        let t = Tree::new(PathBuf::from(DEFUALT_INITIAL_PATHL), get_excludes()).await;
-        t.save(&save_path).expect("Couldn't save tree");
-        t
-    };
+    for name in INDEXES.names() {
+        if let Err(e) = INDEXES.load(&app, &name, options.clone()).await {
+            failed.push(format!("{}: {}", name, e));
+        }
+    }
 
-    let mut tree = GLOBAL_TREE.write().unwrap(); // <- Now safe, no `.await` after
-    *tree = Some(new_tree);
+    if failed.is_empty() { Ok(()) } else { Err(failed.join("\n")) }
+}
 
-    dbg!("Tree loaded");
+#[tauri::command]
+async fn load_index(app: AppHandle, name: String, options: Option<CrawlOptions>) -> Result<(), String> {
+    INDEXES.load(&app, &name, options.unwrap_or_default()).await
+}
 
-    Ok(())
+#[tauri::command]
+fn unload_index(name: String) -> bool {
+    INDEXES.unload(&name)
+}
+
+/// Rebuilds an index from scratch. Without a `profile` its current one is reused
+#[tauri::command]
+async fn rebuild_index(
+    app: AppHandle,
+    name: String,
+    options: Option<CrawlOptions>,
+    profile: Option<ExcludeProfile>
+) -> Result<(), String> {
+    INDEXES.rebuild(&app, &name, options.unwrap_or_default(), profile).await
+}
+
+#[tauri::command]
+fn cancel_index(name: String) -> bool {
+    INDEXES.cancel(&name)
+}
+
+#[tauri::command]
+fn list_indexes() -> Vec<IndexInfo> {
+    INDEXES.list()
+}
+
+/// Registers a new index over `roots`, it's built the next time it's loaded
+#[tauri::command]
+fn add_index(name: String, roots: Vec<PathBuf>, profile: Option<ExcludeProfile>) -> Result<(), String> {
+    let save_path = INDEXES.save_path_for(&name);
+    let roots = roots.iter().map(|root| locations::expand_home(root)).collect();
+
+    INDEXES.add(IndexConfig::new(name, roots, profile.unwrap_or_default(), &save_path))
+}
+
+/// What the first-run setup offers to index, `None` once it's been done
+#[tauri::command]
+fn first_run() -> Option<Vec<SuggestedRoot>> {
+    INDEXES.is_new().then(locations::suggested_roots)
+}
+
+/// Creates the first index from the roots picked during setup. Skipping setup picks none,
+/// searches then walk the home folder until an index is added
+#[tauri::command]
+fn finish_setup(roots: Vec<PathBuf>, profile: Option<ExcludeProfile>) -> Result<(), String> {
+    if roots.is_empty() {
+        return INDEXES.save_registry();
+    }
+
+    add_index("default".to_string(), roots, profile)
+}
+
+#[tauri::command]
+fn remove_index(name: String) -> Result<(), String> {
+    INDEXES.remove(&name)
+}
+
+/// The user's settings as the frontend last saved them, `None` before the first save
+#[tauri::command]
+fn read_config() -> Option<serde_json::Value> {
+    let bytes = fs::read(locations::config_file()).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+#[tauri::command]
+fn write_config(config: serde_json::Value) -> Result<(), String> {
+    let path = locations::config_file();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+
+    let json = serde_json::to_vec_pretty(&config).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| format!("Couldn't save settings: {}", e))
+}
+
+#[tauri::command]
+fn exclude_profiles() -> Vec<ExcludeProfile> {
+    ExcludeProfile::builtin()
+}
+
+/// Payload of `query-chunk`, `generation` is the one sent with `clear` before it.
+/// Chunks of an older query can still be on their way and should be dropped
+#[derive(Debug, Clone, Serialize)]
+struct QueryChunk {
+    generation: u64,
+    results: Vec<SearchResult>,
+}
+
+/// One row of `query-chunk`. Folders and files that are gone have no size
+#[derive(Debug, Clone, Serialize)]
+struct SearchResult {
+    path: String,
+    size: Option<u64>,
+
+    /// `size` written in the units the query was read with
+    size_human: Option<String>,
+
+    /// Only for ranked queries, higher is better
+    score: Option<u32>,
+
+    /// Lines `contains:` found, empty for other queries
+    lines: Vec<LineMatch>,
+}
+
+/// Lines shown under each result of a `contains:` query
+const LINES_PER_RESULT: usize = 3;
+
+/// Files searched per lock of an index
+const INDEX_BATCH: usize = 10_000;
+
+/// A match kept until the best ones are known. Ordered from worst to best,
+/// shorter paths win ties
+#[derive(Debug)]
+struct Ranked {
+    score: u32,
+    path: PathBuf,
+    meta: Option<FileMeta>,
+    lines: Vec<LineMatch>,
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.score
+            .cmp(&other.score)
+            .then_with(|| other.path.as_os_str().len().cmp(&self.path.as_os_str().len()))
+            .then_with(|| other.path.cmp(&self.path))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
 }
 
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Collects matches for the frontend. They're sent in chunks of `chunk_size` as they're
+/// found, or for ranked queries, kept until every file was looked at so the best go first
+struct Results<'q> {
+    query: &'q Query,
+    generation: u64,
+    units: SizeUnits,
+    limit: usize,
+    chunk_size: usize,
+    sender: Sender<QueryChunk>,
+    chunk: Vec<SearchResult>,
+    sent: usize,
 
+    /// The best `limit` so far with the worst on top, `None` if the query isn't ranked
+    best: Option<BinaryHeap<Reverse<Ranked>>>,
+}
+
+impl<'q> Results<'q> {
+    fn new(query: &'q Query, generation: u64, units: SizeUnits, limit: usize, chunk_size: usize, sender: Sender<QueryChunk>) -> Self {
+        let best = query.is_ranked().then(BinaryHeap::new);
+        Results { query, generation, units, limit, chunk_size, sender, chunk: Vec::new(), sent: 0, best }
+    }
+
+    /// Unranked results stop coming once there's enough of them, and any once a newer query started
+    fn is_done(&self) -> bool {
+        !is_latest(self.generation) || (self.best.is_none() && self.sent >= self.limit)
+    }
+
+    /// Runs every file through the query. Files without cached metadata get stat'ed by
+    /// the filters that need it, and once more for their size if they match.
+    /// With `contains:` files are read in parallel, see `Query::for_each_match`
+    fn add<'a, I, P>(&mut self, files: I)
+        where I: Iterator<Item = (P, Option<&'a FileMeta>)>, P: AsRef<Path> + Sync
+    {
+        if self.is_done() {
+            return;
+        }
+
+        // Files that don't match don't get to the callback, so a newer query stops the walk here
+        let generation = self.generation;
+        let files = files.take_while(|_| is_latest(generation));
+
+        let query = self.query;
+        query.for_each_match(files, LINES_PER_RESULT, |path, meta, score, lines| {
+            let path = path.as_ref();
+            match &mut self.best {
+                None => self.send(path, meta.cloned(), None, lines),
+                Some(best) => {
+                    best.push(Reverse(Ranked { score, path: path.to_path_buf(), meta: meta.cloned(), lines }));
+                    if best.len() > self.limit {
+                        best.pop();
+                    }
+                }
+            }
+
+            !self.is_done()
+        });
+    }
+
+    fn send(&mut self, path: &Path, meta: Option<FileMeta>, score: Option<u32>, lines: Vec<LineMatch>) {
+        let meta = meta.or_else(|| FileMeta::read(path));
+        let size = meta.filter(|m| m.kind == FileKind::File).map(|m| m.size);
+
+        self.chunk.push(SearchResult {
+            path: path.to_string_lossy().to_string(),
+            size,
+            size_human: size.map(|size| format_size(size, self.units)),
+            score,
+            lines,
+        });
+        self.sent += 1;
+
+        if self.chunk.len() >= self.chunk_size {
+            let results = std::mem::take(&mut self.chunk);
+            self.sender.send(QueryChunk { generation: self.generation, results }).unwrap();
+        }
+    }
+
+    /// Sends the ranked results best first, and whatever is left in the last chunk.
+    /// Nothing if a newer query started
+    fn finish(mut self) {
+        if !is_latest(self.generation) {
+            return;
+        }
+
+        if let Some(best) = self.best.take() {
+            for Reverse(ranked) in best.into_sorted_vec() {
+                self.send(&ranked.path, ranked.meta, Some(ranked.score), ranked.lines);
+            }
+        }
+
+        if !self.chunk.is_empty() {
+            self.sender.send(QueryChunk { generation: self.generation, results: self.chunk }).unwrap();
+        }
+    }
+}
+
+/// Payload of `parse-error`, spans are byte offsets into `query`
+#[derive(Debug, Clone, Serialize)]
+struct QueryErrors {
+    query: String,
+    errors: Vec<ParseError>,
+}
+
+/// Searches the `indexes` named, or all of them. Indexes that aren't loaded are walked on disk.
+/// Ranked queries, like with `fuzzy:`, send the best `limit` results once the search is done.
+/// Starting another search stops this one
 #[tauri::command]
 async fn stream_query(
     app: AppHandle,
     q: String,
     limit: usize,
-    chunk_size: usize
+    chunk_size: usize,
+    indexes: Option<Vec<String>>,
+    options: ParseOptions
 ) -> Result<(), String> {
     let (sender, receiver) = unbounded();
+    let generation = QUERY_GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
 
     // Spawn filtering in background thread
     let app_clone = app.clone();
 
     std::thread::spawn(move || {
-        let filters: Vec<Node> = Parser::parse(q.clone())
-            .into_iter()
-            .filter(|node| match node {
-                Node::Call { func, args } => {
-                    let dummy_path = PathBuf::from("/").to_path_buf();
-                    func(&dummy_path.to_string_lossy().to_string(), args).is_ok()
-                }
-                Node::Fail(e) => {
-                    app_clone.emit("parse-error", e).unwrap();
-                    false
+        let query = Query::parse_with(&q, &options);
+        if !query.errors().is_empty() {
+            let errors = QueryErrors { query: q.clone(), errors: query.errors().to_vec() };
+            app_clone.emit("parse-error", errors).unwrap();
+        }
+
+        let targets = indexes.as_deref();
+        let mut results = Results::new(&query, generation, options.size_units, limit, chunk_size, sender);
+
+        for tree in INDEXES.trees(targets) {
+            // The tree is only locked while a batch is copied out of it, so the
+            // watcher isn't kept waiting while files are read for `contains:`
+            let mut batches = tree.read().unwrap().batches();
+            while !results.is_done() {
+                let batch = batches.next_batch(&tree.read().unwrap(), INDEX_BATCH);
+                if batch.is_empty() {
+                    break;
                 }
-            })
-            .collect();
-
-        let mut results = Vec::new();
-        let mut total_sent = 0;
-
-        for entry in WalkDir::new(DEFUALT_INITIAL_PATHL)
-            .into_iter()
-            .filter_map(Result::ok)
-            .filter(|e| e.file_type().is_file())
-        {
-            if total_sent >= limit {
-                break;
+                results.add(batch.iter().map(|(path, meta)| (path, meta.as_ref())));
             }
+        }
 
-            let path = entry.path().to_path_buf();
-            let path_str = path.to_string_lossy().to_string();
-
-            let valid = filters.iter().all(|filter| match filter {
-                Node::Call { func, args } => func(&path_str, args).unwrap_or(false),
-                _ => false,
-            });
+        // With no indexes set up yet the home folder is searched instead
+        let mut unloaded = INDEXES.unloaded(targets);
+        if INDEXES.names().is_empty() {
+            let home = locations::home_dir();
+            unloaded.push(IndexConfig::new("home", vec![home], ExcludeProfile::default(), Path::new("")));
+        }
 
-            if valid {
-                results.push(path_str);
-                total_sent += 1;
+        for config in unloaded {
+            if results.is_done() {
+                break;
+            }
 
-                if results.len() >= chunk_size {
-                    sender.send(results.clone()).unwrap();
-                    results.clear();
+            match config.profile.rules.compile(&config.roots) {
+                Ok(excluder) => {
+                    let walk = Walk::new(excluder).map(|path| (path, None));
+                    results.add(walk);
+                }
+                Err(e) => {
+                    // Not the query's fault, so there's nothing in it to underline
+                    let error = ParseError::new(format!("Excludes of {}: {}", config.name, e), Span { start: 0, end: 0 });
+                    app_clone.emit("parse-error", QueryErrors { query: q.clone(), errors: vec![error] }).unwrap();
                 }
             }
         }
 
-        // Send remaining items if any
-        if !results.is_empty() {
-            sender.send(results).unwrap();
-        }
+        results.finish();
     });
 
     // clear UI
-    app.emit("clear", true).unwrap();
+    app.emit("clear", generation).unwrap();
 
     tauri::async_runtime::spawn(async move {
         for chunk in receiver {
@@ -175,7 +423,7 @@ pub fn run() {
         // .setup(|app| {
         //     // This runs once at launch, before frontend is ready.
         //     tauri::async_runtime::spawn(async move {
-        //         match load_tree().await {
+        //         match load_indexes().await {
         //             Ok(_) => println!("Tree loaded at startup."),
         //             Err(e) => eprintln!("Failed to load tree: {e}"),
         //         }
@@ -185,7 +433,23 @@ pub fn run() {
         // })
         .plugin(tauri_plugin_opener::init())
         // << handlers >>
-        .invoke_handler(tauri::generate_handler![read_dir, stream_query])
+        .invoke_handler(tauri::generate_handler![
+            read_dir,
+            load_indexes,
+            load_index,
+            unload_index,
+            rebuild_index,
+            cancel_index,
+            list_indexes,
+            add_index,
+            remove_index,
+            first_run,
+            finish_setup,
+            read_config,
+            write_config,
+            exclude_profiles,
+            stream_query
+        ])
 
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
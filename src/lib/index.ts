@@ -1,6 +1,56 @@
-export type ParseFail = { Invalid: string } | {
-    Conflicting: {
-        name: string,
-        causator: string
+// Emitted as `tree-changed` whenever the index picks up a change on disk
+export type TreeChange = { Created: string }
+    | { Removed: string }
+    | { Renamed: { from: string, to: string } }
+    | { Modified: string }
+
+// Mirrors fs_tree_db::ExcludeProfile, patterns are .gitignore lines
+export type ExcludeProfile = {
+    name: string,
+    rules: {
+        patterns: string[],
+        ignore_files: boolean
     }
-} | { Unexsistent: string }
\ No newline at end of file
+}
+
+// Mirrors src-tauri's IndexInfo, as returned by `list_indexes`
+export type IndexInfo = {
+    name: string,
+    roots: string[],
+    profile: ExcludeProfile,
+    save_path: string,
+    loaded: boolean,
+    building: boolean,
+    files: number | null
+}
+
+// Mirrors fs_tree_db::SuggestedRoot, offered by the first-run setup
+export type SuggestedRoot = {
+    path: string,
+    label: string,
+    recommended: boolean
+}
+
+// Mirrors query::LineMatch, line numbers start at 1
+export type LineMatch = {
+    line: number,
+    text: string
+}
+
+// One row of `query-chunk`, folders have no size.
+// Ranked queries like fuzzy: arrive best first with a score
+export type SearchResult = {
+    path: string,
+    size: number | null,
+    size_human: string | null,
+    score: number | null,
+
+    // What contains: found, empty for other queries
+    lines: LineMatch[]
+}
+
+// Payload of `query-chunk`, only the generation last sent with `clear` is current
+export type QueryChunk = {
+    generation: number,
+    results: SearchResult[]
+}
@@ -1,12 +1,18 @@
 import ParseError from "$lib/components/ParseError.svelte";
 import SearchResult from "$lib/components/SearchResult.svelte";
+import IndexErrors from "$lib/components/IndexErrors.svelte";
+import Settings from "$lib/components/Settings.svelte";
+import Setup from "$lib/components/Setup.svelte";
 import type { Component } from "svelte";
 import { writable } from "svelte/store";
 
 
 const COL_TO_COMP_MAP: Record<string, () => Component> = {
     "SearchResult": () => SearchResult,
-    "ParseError": () => ParseError
+    "ParseError": () => ParseError,
+    "IndexErrors": () => IndexErrors,
+    "Settings": () => Settings,
+    "Setup": () => Setup
 }
 
 export const columns = writable<Record<string, Component>>({})
@@ -0,0 +1,6 @@
+import { writable } from "svelte/store";
+
+// Folders that couldn't be read while building the index
+export type CrawlError = { path: string, error: string }
+
+export const index_errors = writable<CrawlError[]>([]);
@@ -0,0 +1,14 @@
+import { writable } from "svelte/store";
+
+// Snapshot of a running index build, sent as `index-progress`
+export type IndexProgress = {
+  index: string,
+  dirs_visited: number,
+  files_found: number,
+  queue_depth: number,
+  current_path: string,
+  finished: boolean,
+}
+
+// Builds in progress, by index name
+export const index_progress = writable<Record<string, IndexProgress>>({});
@@ -1,3 +1,16 @@
 import { writable } from "svelte/store";
 
-export const parse_error = writable<string | null>(null);
+// Byte offsets into the query, `end` is exclusive
+export type Span = { start: number, end: number }
+
+export type ParseError = {
+    message: string,
+    span: Span,
+    expected: string | null,
+    suggestion: string | null,
+}
+
+// Emitted once per query as `parse-error`
+export type QueryErrors = { query: string, errors: ParseError[] }
+
+export const parse_error = writable<QueryErrors | null>(null);
@@ -1,44 +1,122 @@
 import { writable } from 'svelte/store';
 import { browser } from '$app/environment';
+import { invoke } from '@tauri-apps/api/core';
 
 
+// Mirrors fs_tree_db::CrawlOptions, null means let the crawler decide
+export type CrawlOptions = {
+    workers: number | null,
+    max_io: number | null,
+    auto_tune: boolean
+}
+
+// Mirrors query::ParseOptions
+export type QueryOptions = {
+    // How 01/02/2024 is read
+    date_order: "day_first" | "month_first",
+
+    // Whether 1kb is 1000 or 1024 bytes, in queries and in result sizes
+    size_units: "decimal" | "binary",
+
+    // For has:, name: and parent:, terms can override it with case:, nocase: or smartcase:
+    case: "sensitive" | "insensitive" | "smart",
+    normalization: "none" | "nfc" | "nfkc",
+
+    // resume matches résumé
+    fold_diacritics: boolean,
+
+    // Extension groups for type:, on top of the built-in ones
+    file_types: Record<string, string[]>,
+
+    // How contains: decodes files, auto is UTF-8 or UTF-16 with a byte order mark
+    encoding: "auto" | "utf8" | "utf16le" | "utf16be" | "latin1",
+
+    // Bytes contains: reads per file at most
+    read_limit: number
+}
+
 type UserConfig = {
     // Maximun Number of results to show
-    result_limit: number
+    result_limit: number,
+
+    // Only used when an index has to be rebuilt
+    crawl: CrawlOptions,
+
+    // Indexes searched by queries, null searches all of them
+    search_indexes: string[] | null,
+
+    query: QueryOptions
 }
 
 // Se inicializa con instancias de History en lugar de arreglos simples
 export const default_value: UserConfig = {
-    result_limit: 21
+    result_limit: 21,
+    crawl: {
+        workers: null,
+        max_io: null,
+        auto_tune: false
+    },
+    search_indexes: null,
+    query: {
+        date_order: "day_first",
+        size_units: "decimal",
+        case: "smart",
+        normalization: "nfc",
+        fold_diacritics: false,
+        file_types: {},
+        encoding: "auto",
+        read_limit: 10 * 1024 * 1024
+    }
 };
 
-function load_config(): UserConfig {
-    let config = default_value;
-
-    if (browser && localStorage.getItem('user_config')) {
-        let plain = JSON.parse(localStorage.getItem('user_config') || '');
-
-        // Comprobar claves faltantes y asignar valores por defecto si es necesario
-        for (const key in default_value) {
-            if (!(key in plain)) {
-                // @ts-ignore
-                plain[key] = default_value[key];
-            }
+function with_defaults(plain: any): UserConfig {
+    // Comprobar claves faltantes y asignar valores por defecto si es necesario
+    for (const key in default_value) {
+        if (!(key in plain)) {
+            // @ts-ignore
+            plain[key] = default_value[key];
         }
+    }
+
+    // Options added later still get their defaults
+    plain.query = { ...default_value.query, ...plain.query };
+
+    return plain as unknown as UserConfig;
+}
 
-        config = plain as unknown as UserConfig;
+// Settings used to live in localStorage, they're only read from there until the first save
+function load_legacy(): UserConfig {
+    if (browser && localStorage.getItem('user_config')) {
+        return with_defaults(JSON.parse(localStorage.getItem('user_config') || ''));
     }
 
-    return config;
+    return default_value;
 }
 
-const initial_value: UserConfig = load_config();
-const user_config = writable(initial_value);
+const user_config = writable(load_legacy());
+
+// Nothing is saved until the file has been read, so it never gets overwritten by the defaults
+let loaded = false;
+
+if (browser) {
+    invoke<object | null>("read_config")
+        .then((saved) => {
+            if (saved) user_config.set(with_defaults(saved));
+        })
+        .catch((e) => console.error("Failed to read settings: ", e))
+        .finally(() => {
+            loaded = true;
+
+            // Writes whatever was migrated from localStorage
+            user_config.update((value) => value);
+            localStorage.removeItem('user_config');
+        });
+}
 
+// Saved to config.json in the platform's config dir
 user_config.subscribe((value) => {
-    if (browser) {
-        // Gracias a toJSON, JSON.stringify convertirá correctamente las instancias de History a arreglos.
-        localStorage.setItem('user_config', JSON.stringify(value));
+    if (browser && loaded) {
+        invoke("write_config", { config: value }).catch((e) => console.error("Failed to save settings: ", e));
     }
 });
 